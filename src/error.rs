@@ -5,6 +5,9 @@ use std::io;
 use std::path::PathBuf;
 use std::string::FromUtf8Error;
 
+use crate::parse::TextPosition;
+use crate::units::Length;
+
 type StaticEvent = quick_xml::events::Event<'static>;
 
 #[derive(thiserror::Error, Debug, From)]
@@ -16,7 +19,26 @@ pub enum Error {
     #[error("{0}")]
     TrailingEvents(TrailingEvents),
     #[error("{0}")]
-    ParseLayer(ParseLayer),
+    ParseLayer(ParseError),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("{error} at {position}")]
+pub struct ParseError {
+    pub(crate) error: Box<ParseLayer>,
+    pub(crate) position: TextPosition,
+}
+
+impl ParseError {
+    /// what went wrong
+    pub fn error(&self) -> &ParseLayer {
+        &self.error
+    }
+
+    /// where in the document it went wrong
+    pub fn position(&self) -> TextPosition {
+        self.position
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -62,12 +84,74 @@ pub struct TrailingEvents {
     pub(crate) event: StaticEvent,
 }
 
-#[derive(thiserror::Error, Debug, Constructor)]
+#[derive(thiserror::Error, Debug, Clone, Constructor)]
 #[error("Id `{id}`was not found in document")]
 pub struct MissingId {
     pub(crate) id: String,
 }
 
+impl MissingId {
+    /// the id that was not found
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Id `{id}` is not a <text> element")]
+pub struct NotText {
+    pub(crate) id: String,
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum TextError {
+    #[error("Failed to access text: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to access text: `{0}`")]
+    NotText(NotText),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Layer `{layer}` was not found in document")]
+pub struct MissingLayer {
+    pub(crate) layer: String,
+}
+
+impl MissingLayer {
+    /// the id or name of the layer that was not found
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Layer `{layer}` matches more than one layer in the document: {ids:?}")]
+pub struct AmbiguousLayer {
+    pub(crate) layer: String,
+    /// the ids of every matching layer
+    pub(crate) ids: Vec<String>,
+}
+
+impl AmbiguousLayer {
+    /// the name that was looked up
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+
+    /// the ids of every layer with that name
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum LayerLookupError {
+    #[error("Failed to find layer: `{0}`")]
+    MissingLayer(MissingLayer),
+    #[error("Failed to find layer: `{0}`")]
+    AmbiguousLayer(AmbiguousLayer),
+}
+
 #[derive(thiserror::Error, Debug, From)]
 pub enum ParseLayer {
     #[error("failed to parse layer: `{0}`")]
@@ -78,8 +162,68 @@ pub enum ParseLayer {
     MissingLayerName(MissingLayerName),
     #[error("failed to parse layer: `{0}`")]
     MissingLayerId(MissingLayerId),
+    #[error("failed to parse layer: `{0}`")]
+    MissingObjectEnd(MissingObjectEnd),
+    #[error("failed to parse layer: `{0}`")]
+    UnexpectedEndTag(UnexpectedEndTag),
+    #[error("failed to parse layer: `{0}`")]
+    UnexpectedEof(UnexpectedEof),
+    #[error("failed to parse layer: `{0}`")]
+    ReadXml(ReadXml),
+}
+
+/// The part of a document that was being read when parsing failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParsePhase {
+    /// everything before the first layer
+    Leading,
+    /// the layers and their content
+    Layers,
+    /// everything after the last layer
+    Trailing,
+}
+
+impl std::fmt::Display for ParsePhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phase = match self {
+            Self::Leading => "leading events",
+            Self::Layers => "layers",
+            Self::Trailing => "trailing events",
+        };
+
+        f.write_str(phase)
+    }
 }
 
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Failed to read xml of the {phase}: `{err}`")]
+pub struct ReadXml {
+    pub(crate) err: quick_xml::Error,
+    pub(crate) phase: ParsePhase,
+}
+
+impl ReadXml {
+    /// the error of the xml reader
+    pub fn error(&self) -> &quick_xml::Error {
+        &self.err
+    }
+
+    /// the part of the document that was being read
+    pub fn phase(&self) -> ParsePhase {
+        self.phase
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Closing tag `</{name}>` does not belong to any element")]
+pub struct UnexpectedEndTag {
+    pub(crate) name: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("The document ended before the root `</svg>` tag")]
+pub struct UnexpectedEof;
+
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error("missing `id` attribute for <g> attribute of layer, maybe it was not UTF8? `{element:?}`")]
 pub struct MissingLayerId {
@@ -93,12 +237,47 @@ pub struct ParseObject {
     pub(crate) layer_name: String,
 }
 
+/// A rectangle or image that was not fully parsed: either it was kept as plain xml in
+/// lenient mode since its `id`, `width` or `height` could not be parsed, or its `x`, `y` or
+/// `transform` could not be parsed and the default was used instead
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Object in layer {layer_name} at {position} was not fully parsed: {error}")]
+pub struct ParseWarning {
+    pub(crate) error: IdentifierError,
+    pub(crate) layer_name: String,
+    pub(crate) position: TextPosition,
+}
+
+impl ParseWarning {
+    /// what could not be parsed
+    pub fn error(&self) -> &IdentifierError {
+        &self.error
+    }
+
+    /// the name of the layer the object is in
+    pub fn layer_name(&self) -> &str {
+        &self.layer_name
+    }
+
+    /// where the object starts in the document
+    pub fn position(&self) -> TextPosition {
+        self.position
+    }
+}
+
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error("Missing end of group attribute for layer {layer_name}")]
 pub struct MissingLayerEnd {
     pub(crate) layer_name: String,
 }
 
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Missing closing tag for <{element_name}> element in layer {layer_name}")]
+pub struct MissingObjectEnd {
+    pub(crate) element_name: String,
+    pub(crate) layer_name: String,
+}
+
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error("Layer name was missing or not UTF8 for event: {event:?}")]
 pub struct MissingLayerName {
@@ -113,13 +292,20 @@ pub struct DimensionUtf8 {
 }
 
 #[derive(thiserror::Error, Debug, Constructor)]
-#[error("Failed to parse `{dimension}` parameter to utf8 string; error: `{error}`")]
+#[error("Failed to parse `{dimension}` parameter as a length; error: `{error}`")]
 pub struct DimensionParse {
-    error: std::num::ParseFloatError,
+    error: ParseLengthError,
     dimension: DimensionOrId,
 }
 
-#[derive(Debug, derive_more::Display)]
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("`{value}` is not a number with an optional px, mm, cm, in, pt, pc, Q or % unit; error: `{error}`")]
+pub struct ParseLengthError {
+    error: std::num::ParseFloatError,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, derive_more::Display)]
 pub enum DimensionOrId {
     #[display(fmt = "width")]
     Width,
@@ -127,6 +313,10 @@ pub enum DimensionOrId {
     Height,
     #[display(fmt = "id")]
     Id,
+    #[display(fmt = "x")]
+    X,
+    #[display(fmt = "y")]
+    Y,
 }
 
 #[derive(thiserror::Error, Debug, From)]
@@ -137,14 +327,32 @@ pub enum IdentifierError {
     DimensionParse(DimensionParse),
     #[error("Failed to parse identifier: `{0}`")]
     MissingObjectIdentifier(MissingObjectIdentifier),
+    #[error("Failed to parse identifier: `{0}`")]
+    ParseTransform(ParseTransformError),
 }
 
+#[derive(thiserror::Error, Debug, From)]
+pub enum AttributeError {
+    #[error("Failed to update attribute: `{0}`")]
+    Identifier(IdentifierError),
+    #[error("Failed to update attribute: `{0}`")]
+    MissingLayerId(MissingLayerId),
+    #[error("Failed to update attribute: `{0}`")]
+    MissingLayerName(MissingLayerName),
+    #[error("Failed to update attribute: `{0}`")]
+    NoAttributes(NoAttributes),
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("only elements have attributes, not text, comments or closing tags")]
+pub struct NoAttributes;
+
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error("One of width ({width:?}) / height ({height:?}) / id ({id:?} was missing for element {element:?}")]
 pub struct MissingObjectIdentifier {
     element: BytesStart<'static>,
-    width: Option<f64>,
-    height: Option<f64>,
+    width: Option<Length>,
+    height: Option<Length>,
     id: Option<String>,
 }
 
@@ -158,39 +366,374 @@ pub enum EncodingError {
     UnknownMime(UnknownMime),
     #[error("Error while encoding image: `{0}`")]
     WrongEncoding(WrongEncoding),
+    #[error("Error while encoding image: `{0}`")]
+    TranscodeImage(TranscodeImage),
 }
 
 #[derive(thiserror::Error, Debug, Constructor)]
-#[error("failed to open file at {}; error: {error}", "path.display()")]
+#[error("failed to open file at {}; error: {error}", path.display())]
 pub struct OpenFile {
     error: io::Error,
     path: PathBuf,
 }
 
 #[derive(thiserror::Error, Debug, Constructor)]
-#[error(
-    "failed to read bytes of file {} after it was opened; error: {error}",
-    "path.display()"
-)]
+#[error("failed to read bytes of image {origin}; error: {error}")]
 pub struct ReadBytes {
     error: io::Error,
-    path: PathBuf,
+    origin: ImageSource,
 }
 
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error(
-    "image at path {} has an unknown mime type. figure_second only handles PNG encoded images",
-    "path.display()"
+    "image {origin} has an unknown mime type. figure_second only handles image formats supported by the `image` crate"
 )]
 pub struct UnknownMime {
-    path: PathBuf,
+    origin: ImageSource,
 }
 
 #[derive(thiserror::Error, Debug, Constructor)]
 #[error(
-    "image at path {} is not PNG encoded. Images must be png encoded currently",
-    "path.display()"
+    "image {origin} cannot be embedded in an svg with this encoding. Transcode it to PNG instead"
 )]
 pub struct WrongEncoding {
+    origin: ImageSource,
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("failed to encode image {origin}; error: {error}")]
+pub struct TranscodeImage {
+    error: image::ImageError,
+    origin: ImageSource,
+}
+
+/// where the bytes of an image being encoded came from
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ImageSource {
+    #[display(fmt = "at path {}", "_0.display()")]
+    Path(PathBuf),
+    #[display(fmt = "from memory")]
+    Memory,
+    #[display(fmt = "from reader")]
+    Reader,
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum SvgError {
+    #[error("Error while reading svg document: `{0}`")]
+    OpenFile(OpenFile),
+    #[error("Error while reading svg document: `{0}`")]
+    ReadSvg(ReadSvg),
+    #[error("Error while reading svg document: `{0}`")]
+    MissingSvgRoot(MissingSvgRoot),
+    #[error("Error while reading svg document: `{0}`")]
+    Viewport(ViewportError),
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum ViewportError {
+    #[error("Failed to determine document units: `{0}`")]
+    DimensionParse(DimensionParse),
+    #[error("Failed to determine document units: `{0}`")]
+    InvalidViewBox(InvalidViewBox),
+    #[error("Failed to determine document units: `{0}`")]
+    MissingViewBox(MissingViewBox),
+    #[error("Failed to determine document units: `{0}`")]
+    MissingSvgRoot(MissingSvgRoot),
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum DimensionError {
+    #[error("Failed to get dimensions: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to get dimensions: `{0}`")]
+    Viewport(ViewportError),
+    #[error("Failed to get dimensions: `{0}`")]
+    ParseTransform(ParseTransformError),
+    #[error("Failed to get dimensions: `{0}`")]
+    Select(SelectError),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("`{value}` is not a valid transform list: {reason}")]
+pub struct ParseTransformError {
+    value: String,
+    reason: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("failed to read xml event; error: {err}")]
+pub struct ReadSvg {
+    pub(crate) err: quick_xml::Error,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("document does not contain a complete root <svg> element")]
+pub struct MissingSvgRoot;
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("viewBox `{view_box}` of the root <svg> element is not four numbers with a positive width and height")]
+pub struct InvalidViewBox {
+    view_box: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("root <svg> element has neither a viewBox nor an absolute width and height")]
+pub struct MissingViewBox;
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum EmbedSvgError {
+    #[error("Failed to embed svg document: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to embed svg document: `{0}`")]
+    Viewport(ViewportError),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Failed to embed the image linked from `{id}`: `{error}`")]
+pub struct EmbedLinkedImage {
+    pub(crate) id: String,
+    pub(crate) error: EncodingError,
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum ExtractImageError {
+    #[error("Failed to extract embedded image: `{0}`")]
+    DecodeImage(DecodeImage),
+    #[error("Failed to extract embedded image: `{0}`")]
+    WriteFile(WriteFile),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("base64 data of image `{id}` could not be decoded; error: {error}")]
+pub struct DecodeImage {
+    pub(crate) id: String,
+    pub(crate) error: base64::DecodeError,
+}
+
+impl DecodeImage {
+    /// the id of the image
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// why the base64 data could not be decoded
+    pub fn error(&self) -> &base64::DecodeError {
+        &self.error
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("failed to write file at {}; error: {error}", path.display())]
+pub struct WriteFile {
+    error: io::Error,
     path: PathBuf,
 }
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum ImageDataError {
+    #[error("Failed to read image data: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to read image data: `{0}`")]
+    NotImage(NotImage),
+    #[error("Failed to read image data: `{0}`")]
+    NotEmbedded(NotEmbedded),
+    #[error("Failed to read image data: `{0}`")]
+    MalformedDataUri(MalformedDataUri),
+    #[error("Failed to read image data: `{0}`")]
+    UnsupportedEncoding(UnsupportedEncoding),
+    #[error("Failed to read image data: `{0}`")]
+    DecodeImage(DecodeImage),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("Id `{id}` is not an <image> element")]
+pub struct NotImage {
+    pub(crate) id: String,
+}
+
+impl NotImage {
+    /// the id of the object
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("image `{id}` links to a file instead of embedding its data")]
+pub struct NotEmbedded {
+    pub(crate) id: String,
+}
+
+impl NotEmbedded {
+    /// the id of the image
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("image `{id}` has a data URI that is not `data:<mime>;base64,<data>`")]
+pub struct MalformedDataUri {
+    pub(crate) id: String,
+}
+
+impl MalformedDataUri {
+    /// the id of the image
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("image `{id}` has a data URI `data:{header},...` that is not base64 encoded")]
+pub struct UnsupportedEncoding {
+    pub(crate) id: String,
+    pub(crate) header: String,
+}
+
+impl UnsupportedEncoding {
+    /// the id of the image
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// everything between `data:` and the first `,` of the data URI
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("failed to decode {mime} image; error: {error}")]
+pub struct DecodePixels {
+    pub(crate) mime: String,
+    pub(crate) error: image::ImageError,
+}
+
+impl DecodePixels {
+    /// the MIME type of the image
+    pub fn mime(&self) -> &str {
+        &self.mime
+    }
+
+    /// why the pixels could not be decoded
+    pub fn error(&self) -> &image::ImageError {
+        &self.error
+    }
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum ResampleError {
+    #[error("Failed to embed resampled image: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to embed resampled image: `{0}`")]
+    Dimension(DimensionError),
+    #[error("Failed to embed resampled image: `{0}`")]
+    Encoding(EncodingError),
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum FitError {
+    #[error("Failed to fit image into placeholder: `{0}`")]
+    MissingId(MissingId),
+    #[error("Failed to fit image into placeholder: `{0}`")]
+    Dimension(DimensionError),
+    #[error("Failed to fit image into placeholder: `{0}`")]
+    Encoding(EncodingError),
+    #[error("Failed to fit image into placeholder: `{0}`")]
+    Attribute(AttributeError),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("`{pattern}` is not a valid pattern; error: {error}")]
+pub struct InvalidPattern {
+    pub(crate) pattern: String,
+    pub(crate) error: regex::Error,
+}
+
+impl InvalidPattern {
+    /// the pattern as it was given
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// why it is not a valid regular expression
+    pub fn error(&self) -> &regex::Error {
+        &self.error
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("{selector} did not match any object in the document")]
+pub struct NoMatch {
+    pub(crate) selector: String,
+}
+
+impl NoMatch {
+    /// a description of the selector
+    pub fn selector(&self) -> &str {
+        &self.selector
+    }
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("{selector} matches more than one object in the document: {ids:?}")]
+pub struct AmbiguousMatch {
+    pub(crate) selector: String,
+    /// the ids of every matching object
+    pub(crate) ids: Vec<String>,
+}
+
+impl AmbiguousMatch {
+    /// a description of the selector
+    pub fn selector(&self) -> &str {
+        &self.selector
+    }
+
+    /// the ids of every matching object
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum SelectError {
+    #[error("Failed to select object: `{0}`")]
+    NoMatch(NoMatch),
+    #[error("Failed to select object: `{0}`")]
+    AmbiguousMatch(AmbiguousMatch),
+}
+
+#[derive(thiserror::Error, Debug, From)]
+pub enum SelectImageError {
+    #[error("Failed to fill selected object: `{0}`")]
+    Select(SelectError),
+    #[error("Failed to fill selected object: `{0}`")]
+    MissingId(MissingId),
+}
+
+#[derive(thiserror::Error, Debug, Constructor)]
+#[error("`{query}` is not a valid query at byte {position}: {reason}")]
+pub struct ParseQueryError {
+    query: String,
+    position: usize,
+    reason: &'static str,
+}
+
+impl ParseQueryError {
+    /// the query as it was given
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// the byte offset into the query where parsing failed
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// what was wrong at that position
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
@@ -0,0 +1,166 @@
+use super::units::{Length, Unit};
+
+/// How an image is fit into a placeholder whose aspect ratio differs from its own, see
+/// [`Inkscape::id_to_image_fit`](crate::Inkscape::id_to_image_fit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// stretch the image to fill the placeholder (`preserveAspectRatio="none"`)
+    Stretch,
+    /// show the whole image, leaving space on two sides of it
+    /// (`preserveAspectRatio="{align} meet"`)
+    Contain(Align),
+    /// fill the placeholder, cropping the pixels of the image that fall outside of it.
+    /// The pixels are cropped before embedding, so no renderer has to clip them
+    Cover(Align),
+    /// keep the width of the placeholder and change its height to the aspect ratio
+    /// of the image
+    KeepWidth,
+}
+
+impl Default for Fit {
+    /// the default of SVG when an `<image>` has no `preserveAspectRatio`
+    fn default() -> Self {
+        Self::Contain(Align::default())
+    }
+}
+
+impl Fit {
+    /// the `preserveAspectRatio` of the `<image>` once the image has been fit into it
+    pub(crate) fn preserve_aspect_ratio(&self) -> String {
+        match self {
+            Self::Contain(align) => format!("{} meet", align.name()),
+            // the pixels already have the aspect ratio of the element
+            Self::Stretch | Self::Cover(_) | Self::KeepWidth => "none".to_string(),
+        }
+    }
+}
+
+/// Where an image is placed in a placeholder it does not fill in one direction,
+/// as in `preserveAspectRatio`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    XMinYMin,
+    XMidYMin,
+    XMaxYMin,
+    XMinYMid,
+    #[default]
+    XMidYMid,
+    XMaxYMid,
+    XMinYMax,
+    XMidYMax,
+    XMaxYMax,
+}
+
+impl Align {
+    /// the name of the alignment in a `preserveAspectRatio` attribute
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::XMinYMin => "xMinYMin",
+            Self::XMidYMin => "xMidYMin",
+            Self::XMaxYMin => "xMaxYMin",
+            Self::XMinYMid => "xMinYMid",
+            Self::XMidYMid => "xMidYMid",
+            Self::XMaxYMid => "xMaxYMid",
+            Self::XMinYMax => "xMinYMax",
+            Self::XMidYMax => "xMidYMax",
+            Self::XMaxYMax => "xMaxYMax",
+        }
+    }
+
+    /// how far along the free space the image is placed horizontally and vertically,
+    /// from 0 (min) to 1 (max)
+    fn fractions(&self) -> (f64, f64) {
+        let x = match self {
+            Self::XMinYMin | Self::XMinYMid | Self::XMinYMax => 0.0,
+            Self::XMidYMin | Self::XMidYMid | Self::XMidYMax => 0.5,
+            Self::XMaxYMin | Self::XMaxYMid | Self::XMaxYMax => 1.0,
+        };
+        let y = match self {
+            Self::XMinYMin | Self::XMidYMin | Self::XMaxYMin => 0.0,
+            Self::XMinYMid | Self::XMidYMid | Self::XMaxYMid => 0.5,
+            Self::XMinYMax | Self::XMidYMax | Self::XMaxYMax => 1.0,
+        };
+
+        (x, y)
+    }
+}
+
+/// crop `image` to the aspect ratio of a `width` by `height` placeholder, keeping the part
+/// of it at `align`. `None` if the image already has that aspect ratio or has no pixels
+pub(crate) fn crop(
+    image: &image::DynamicImage,
+    (width, height): (f64, f64),
+    align: Align,
+) -> Option<image::DynamicImage> {
+    let (image_width, image_height) = (image.width(), image.height());
+    if image_width == 0 || image_height == 0 {
+        return None;
+    }
+
+    let aspect = width / height;
+    if !aspect.is_finite() || aspect <= 0.0 {
+        return None;
+    }
+
+    let (align_x, align_y) = align.fractions();
+
+    let crop_width = ((image_height as f64 * aspect).round() as u32).clamp(1, image_width);
+    let crop_height = ((image_width as f64 / aspect).round() as u32).clamp(1, image_height);
+
+    let (x, y, crop_width, crop_height) = if crop_width < image_width {
+        let x = ((image_width - crop_width) as f64 * align_x).round() as u32;
+        (x, 0, crop_width, image_height)
+    } else if crop_height < image_height {
+        let y = ((image_height - crop_height) as f64 * align_y).round() as u32;
+        (0, y, image_width, crop_height)
+    } else {
+        return None;
+    };
+
+    Some(image.crop_imm(x, y, crop_width, crop_height))
+}
+
+/// the new `height` of a placeholder that is shown `shown_width` by `shown_height` (after
+/// its transforms), so that it is shown with the aspect ratio of `image`. The height keeps
+/// its unit unless that is relative to the document, in which case `user_height` is used
+pub(crate) fn keep_width_height(
+    image: &image::DynamicImage,
+    height: Length,
+    user_height: f64,
+    (shown_width, shown_height): (f64, f64),
+) -> Length {
+    let scale = (shown_width / shown_height) * (image.height() as f64 / image.width() as f64);
+    if !scale.is_finite() || scale <= 0.0 {
+        return height;
+    }
+
+    match height.unit {
+        Unit::Percent => Length::user(user_height * scale),
+        unit => Length::new(height.value * scale, unit),
+    }
+}
+
+#[test]
+fn crop_to_placeholder() {
+    let image = image::DynamicImage::new_rgb8(200, 100);
+
+    // a square placeholder keeps the middle, left or right of a wide image
+    let cropped = crop(&image, (10.0, 10.0), Align::XMidYMid).unwrap();
+    assert_eq!((cropped.width(), cropped.height()), (100, 100));
+
+    let tall = image::DynamicImage::new_rgb8(100, 300);
+    let cropped = crop(&tall, (20.0, 10.0), Align::XMinYMax).unwrap();
+    assert_eq!((cropped.width(), cropped.height()), (100, 50));
+
+    assert!(crop(&image, (40.0, 20.0), Align::XMidYMid).is_none());
+
+    let empty = image::DynamicImage::new_rgb8(0, 100);
+    assert!(crop(&empty, (10.0, 10.0), Align::XMidYMid).is_none());
+
+    let height = keep_width_height(&image, Length::new(20.0, Unit::Mm), 20.0, (30.0, 20.0));
+    assert_eq!(height, Length::new(15.0, Unit::Mm));
+    assert_eq!(
+        keep_width_height(&empty, Length::new(20.0, Unit::Mm), 20.0, (30.0, 20.0)),
+        Length::new(20.0, Unit::Mm)
+    );
+}
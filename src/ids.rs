@@ -0,0 +1,424 @@
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::BytesCData;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+use quick_xml::name::QName;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Generates ids that are not used anywhere else in a document
+pub(crate) struct IdGenerator {
+    used: HashSet<String>,
+}
+
+impl IdGenerator {
+    pub(crate) fn new(used: HashSet<String>) -> Self {
+        Self { used }
+    }
+
+    /// a new id made of `base` followed by a number, the same way inkscape names
+    /// objects `layer3` or `rect286`. Any number already at the end of `base` is replaced
+    pub(crate) fn generate(&mut self, base: &str) -> String {
+        let base = base.trim_end_matches(|c: char| c.is_ascii_digit() || c == '-');
+
+        let id = (1..)
+            .map(|n| format!("{base}{n}"))
+            .find(|id| !self.used.contains(id))
+            .unwrap();
+
+        self.used.insert(id.clone());
+
+        id
+    }
+}
+
+/// rename the `id` attribute of an element according to `renames`, along with every
+/// reference to a renamed id in its other attributes (`href="#id"`, `fill="url(#id)"`)
+pub(crate) fn rename_in_element(
+    element: &mut BytesStart<'static>,
+    renames: &HashMap<String, String>,
+) {
+    let mut new_element = element.to_owned();
+    new_element.clear_attributes();
+
+    let atts = element.attributes().filter_map(Result::ok).map(|att| {
+        // ids are compared unescaped, `a&amp;b` is the id `a&b`
+        let value = match att.unescape_value() {
+            Ok(value) => value,
+            Err(_) => return att,
+        };
+
+        let new_value = match att.key {
+            QName(b"id") => renames.get(value.as_ref()).cloned(),
+            QName(b"href") | QName(b"xlink:href") => value
+                .strip_prefix('#')
+                .and_then(|id| renames.get(id))
+                .map(|id| format!("#{id}")),
+            _ => rename_references(&value, renames),
+        };
+
+        match new_value {
+            Some(new_value) => Attribute {
+                key: att.key,
+                value: Cow::Owned(quick_xml::escape::escape(&new_value).as_bytes().to_vec()),
+            },
+            None => att,
+        }
+    });
+
+    new_element.extend_attributes(atts);
+    *element = new_element.into_owned();
+}
+
+/// replace every `url(#id)` in `value` of a renamed id, returning `None`
+/// if nothing was replaced. The id may be quoted (`url("#id")`) and surrounded by
+/// whitespace
+pub(crate) fn rename_references(value: &str, renames: &HashMap<String, String>) -> Option<String> {
+    const URL: &str = "url(";
+
+    if !value.contains(URL) {
+        return None;
+    }
+
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut replaced = false;
+
+    while let Some(start) = rest.find(URL) {
+        let (before, after) = rest.split_at(start + URL.len());
+        out.push_str(before);
+
+        // everything up to the `#`, which is whitespace and an optional quote
+        let inner = after.trim_start();
+        let inner = inner.strip_prefix(['"', '\'']).unwrap_or(inner);
+        let hash = after.len() - inner.len();
+
+        let id = match inner.strip_prefix('#') {
+            Some(id) => id,
+            None => {
+                rest = after;
+                continue;
+            }
+        };
+        let id_end = id
+            .find(|c: char| c == ')' || c == '"' || c == '\'' || c.is_whitespace())
+            .unwrap_or(id.len());
+        let id = &id[..id_end];
+
+        out.push_str(&after[..=hash]);
+        match renames.get(id) {
+            Some(new_id) => {
+                out.push_str(new_id);
+                replaced = true;
+            }
+            None => out.push_str(id),
+        }
+
+        rest = &after[hash + 1 + id.len()..];
+    }
+
+    out.push_str(rest);
+
+    replaced.then_some(out)
+}
+
+/// rename the ids in the stylesheet of a `<style>` element, if `event` is its text. See
+/// [`rename_in_stylesheet`] for `scope`.
+///
+/// `in_style` keeps track of whether the events so far are inside of a `<style>` element
+pub(crate) fn rename_in_style(
+    event: &mut Event<'static>,
+    in_style: &mut bool,
+    renames: &HashMap<String, String>,
+    scope: Option<&str>,
+) {
+    match event {
+        Event::Start(element) if element.name() == QName(b"style") => *in_style = true,
+        Event::End(end) if end.name() == QName(b"style") => *in_style = false,
+        Event::Text(text) if *in_style => {
+            let css = match text.unescape() {
+                Ok(css) => css.into_owned(),
+                Err(_) => return,
+            };
+            if let Some(new_css) = rename_in_stylesheet(&css, renames, scope) {
+                *text = BytesText::new(&new_css).into_owned();
+            }
+        }
+        Event::CData(text) if *in_style => {
+            let css = String::from_utf8_lossy(text);
+            if let Some(new_css) = rename_in_stylesheet(&css, renames, scope) {
+                *text = BytesCData::new(new_css);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// rename every `#id` selector and `url(#id)` of a renamed id in the text of a `<style>`
+/// element, returning `None` if nothing changed.
+///
+/// With a `scope`, every rule only applies inside of the element with that id, so that
+/// rules such as `* {...}` of an embedded document leave the rest of the document alone
+pub(crate) fn rename_in_stylesheet(
+    css: &str,
+    renames: &HashMap<String, String>,
+    scope: Option<&str>,
+) -> Option<String> {
+    let mut out = String::with_capacity(css.len());
+    copy_rules(css, renames, scope, &mut out);
+
+    (out != css).then_some(out)
+}
+
+/// copy the rules of a stylesheet (or of a block such as `@media`) to `out`,
+/// see [`rename_in_stylesheet`]
+fn copy_rules(css: &str, renames: &HashMap<String, String>, scope: Option<&str>, out: &mut String) {
+    let with_references = |text: &str| rename_references(text, renames).unwrap_or(text.into());
+    let mut rest = css;
+
+    loop {
+        // whitespace and comments between rules are kept as they are
+        let trimmed = skip_trivia(rest);
+        out.push_str(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+
+        if rest.is_empty() {
+            return;
+        }
+
+        let prelude = prelude_end(rest);
+        let block = if rest[prelude..].starts_with('{') {
+            block_end(&rest[prelude..]) + prelude
+        } else {
+            // a statement like `@import url(...);`, or a rule that is never closed
+            prelude
+        };
+        let end = (block + 1).min(rest.len());
+
+        if let Some(at_rule) = rest.strip_prefix('@') {
+            let name_end = at_rule
+                .find(|c: char| !(c.is_alphanumeric() || c == '-'))
+                .unwrap_or(at_rule.len());
+
+            match &at_rule[..name_end] {
+                // conditional rules hold style rules of their own
+                "media" | "supports" | "container" | "layer" | "document" if block > prelude => {
+                    out.push_str(&rest[..=prelude]);
+                    copy_rules(&rest[prelude + 1..block], renames, scope, out);
+                    out.push_str(&rest[block..end]);
+                }
+                // `@font-face`, `@keyframes`, `@import`, ...
+                _ => out.push_str(&with_references(&rest[..end])),
+            }
+        } else {
+            out.push_str(&selectors(&rest[..prelude], renames, scope));
+            out.push_str(&with_references(&rest[prelude..end]));
+        }
+
+        rest = &rest[end..];
+    }
+}
+
+/// rename the ids in a list of selectors, and limit each of them to `scope`
+fn selectors(list: &str, renames: &HashMap<String, String>, scope: Option<&str>) -> String {
+    let mut out = String::with_capacity(list.len());
+
+    for (index, selector) in split_selectors(list).into_iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+
+        let trimmed = selector.trim_start();
+        out.push_str(&selector[..selector.len() - trimmed.len()]);
+        if let (Some(scope), false) = (scope, trimmed.is_empty()) {
+            out.push('#');
+            out.push_str(scope);
+            out.push(' ');
+        }
+
+        let mut rest = trimmed;
+        while let Some(position) = rest.find(['#', '"', '\'', '[']) {
+            out.push_str(&rest[..position]);
+            rest = &rest[position..];
+
+            // strings and attribute selectors are copied as they are
+            if !rest.starts_with('#') {
+                let end = skip_token(rest);
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+
+            let id = &rest[1..];
+            let id_end = id
+                .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(id.len());
+            let id = &id[..id_end];
+
+            out.push('#');
+            out.push_str(renames.get(id).map(String::as_str).unwrap_or(id));
+            rest = &rest[1 + id.len()..];
+        }
+        out.push_str(rest);
+    }
+
+    out
+}
+
+/// split a list of selectors on the commas that are not inside of a string, attribute
+/// selector or `:is(...)`
+fn split_selectors(list: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut depth: usize = 0;
+    let mut index = 0;
+
+    while index < list.len() {
+        match list.as_bytes()[index] {
+            b'"' | b'\'' => {
+                index += skip_token(&list[index..]);
+                continue;
+            }
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b',' if depth == 0 => {
+                parts.push(&list[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+    parts.push(&list[start..]);
+
+    parts
+}
+
+/// `css` without the whitespace, comments and html comment markers at its start
+fn skip_trivia(mut css: &str) -> &str {
+    loop {
+        let trimmed = css.trim_start();
+        css = if let Some(comment) = trimmed.strip_prefix("/*") {
+            comment.find("*/").map_or("", |end| &comment[end + 2..])
+        } else if let Some(rest) = trimmed.strip_prefix("<!--") {
+            rest
+        } else if let Some(rest) = trimmed.strip_prefix("-->") {
+            rest
+        } else {
+            return trimmed;
+        };
+    }
+}
+
+/// the length of the string, comment or attribute selector at the start of `css`, or 1
+/// for any other character
+fn skip_token(css: &str) -> usize {
+    let close = match css.as_bytes().first() {
+        Some(quote @ (b'"' | b'\'')) => *quote,
+        Some(b'[') => b']',
+        Some(b'/') if css.starts_with("/*") => {
+            return css[2..].find("*/").map_or(css.len(), |end| end + 4)
+        }
+        _ => return 1,
+    };
+
+    let bytes = css.as_bytes();
+    let mut index = 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            byte if byte == close => return index + 1,
+            b'"' | b'\'' if close == b']' => index += skip_token(&css[index..]) - 1,
+            _ => (),
+        }
+        index += 1;
+    }
+
+    css.len()
+}
+
+/// the position of the `{` or `;` that ends the prelude of the rule at the start of `css`
+fn prelude_end(css: &str) -> usize {
+    let mut index = 0;
+    while index < css.len() {
+        match css.as_bytes()[index] {
+            b'{' | b';' => return index,
+            b'"' | b'\'' | b'/' => index += skip_token(&css[index..]),
+            _ => index += 1,
+        }
+    }
+
+    css.len()
+}
+
+/// the position of the `}` that closes the block starting at the beginning of `css`
+fn block_end(css: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut index = 0;
+    while index < css.len() {
+        match css.as_bytes()[index] {
+            b'{' => depth += 1,
+            b'}' if depth <= 1 => return index,
+            b'}' => depth -= 1,
+            b'"' | b'\'' | b'/' => {
+                index += skip_token(&css[index..]);
+                continue;
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+
+    css.len()
+}
+
+#[test]
+fn generate_unique_ids() {
+    let used = ["layer1", "layer2", "rect1"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let mut ids = IdGenerator::new(used);
+
+    assert_eq!(ids.generate("layer"), "layer3");
+    assert_eq!(ids.generate("layer"), "layer4");
+    assert_eq!(ids.generate("rect286"), "rect2");
+    assert_eq!(ids.generate("path-12"), "path1");
+}
+
+#[test]
+fn rename_in_css() {
+    let renames = [("p1", "rect286-p1"), ("patch_1", "rect286-patch_1")]
+        .into_iter()
+        .map(|(id, new_id)| (id.to_string(), new_id.to_string()))
+        .collect::<HashMap<_, _>>();
+
+    assert_eq!(
+        rename_references(r##"fill: url( "#p1" ); stroke: url(#other)"##, &renames).unwrap(),
+        r##"fill: url( "#rect286-p1" ); stroke: url(#other)"##
+    );
+    assert_eq!(
+        rename_references("url(data:image/png;base64,AAAA)", &renames),
+        None
+    );
+
+    let css = r##"*{stroke-linejoin: round} #patch_1, a[href="#p1"] {fill: url('#p1')}
+/* #patch_1 */ @media print { #patch_1:hover { opacity: 0 } } @import url("print.css");"##;
+    assert_eq!(
+        rename_in_stylesheet(css, &renames, Some("rect286")).unwrap(),
+        r##"#rect286 *{stroke-linejoin: round} #rect286 #rect286-patch_1, #rect286 a[href="#p1"] {fill: url('#rect286-p1')}
+/* #patch_1 */ @media print { #rect286 #rect286-patch_1:hover { opacity: 0 } } @import url("print.css");"##
+    );
+
+    assert_eq!(
+        rename_in_stylesheet("#patch_1{fill:url(#p1)}", &renames, None).unwrap(),
+        "#rect286-patch_1{fill:url(#rect286-p1)}"
+    );
+    assert_eq!(
+        rename_in_stylesheet(".a { fill: red }", &renames, None),
+        None
+    );
+}
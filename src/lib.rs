@@ -1,16 +1,49 @@
 mod error;
+mod fit;
+mod ids;
 mod object;
 mod parse;
+mod query;
+mod selector;
+mod style;
+mod svg;
+mod template;
+mod text;
+mod transform;
+mod units;
 
-use error::*;
+pub use error::*;
 
+pub use fit::{Align, Fit};
 pub use object::EncodedImage;
+pub use object::Resample;
+pub use object::Transcode;
+pub use object::{
+    Geometry, Group, HrefAttribute, Image, ImageData, ImageHref, ImageOptions, Object, ObjectKind,
+    RawXml, Rectangle,
+};
+pub use parse::{ParseOptions, TextPosition};
+pub use query::{Handle, Query, SelectedMut};
+pub use selector::{FillReport, Selector};
+pub use style::Style;
+pub use svg::SvgDocument;
+pub use svg::SvgPlacement;
+pub use template::TemplateReport;
+pub use text::Text;
+pub use transform::{BoundingBox, Transform};
+pub use units::{Axis, Length, Unit, Viewport};
 
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
 use quick_xml::events::Event;
 use quick_xml::name::QName;
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::BufRead;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct Inkscape {
@@ -19,16 +52,33 @@ pub struct Inkscape {
     trailing_events: Vec<Event<'static>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Layer {
     id: String,
     name: String,
-    header: Event<'static>,
+    header: BytesStart<'static>,
     content: Vec<object::Object>,
     footer: Event<'static>,
 }
 
 impl Layer {
+    /// an empty layer, as inkscape creates it
+    fn new(id: String, name: String) -> Self {
+        let header = BytesStart::new("g").with_attributes([
+            ("inkscape:label", name.as_str()),
+            ("inkscape:groupmode", "layer"),
+            ("id", id.as_str()),
+        ]);
+
+        Self {
+            id,
+            name,
+            header,
+            content: Vec::new(),
+            footer: Event::End(BytesEnd::new("g")),
+        }
+    }
+
     pub fn id(&self) -> &str {
         &self.id
     }
@@ -37,48 +87,111 @@ impl Layer {
         &self.name
     }
 
-    /// make a layer visible
-    pub fn set_visible(&mut self) {
-        let elem = if let Event::Start(elem) = &mut self.header {
-            elem
-        } else {
-            panic!("miss parsed a layer, the header should be Event::Start");
-        };
+    /// change the name of the layer shown in inkscape (its `inkscape:label`)
+    pub fn set_name(&mut self, name: &str) {
+        object::set_attribute(&mut self.header, "inkscape:label", Some(name));
+        self.name = name.to_string();
+    }
+
+    /// the parsed `style` attribute of the layer
+    pub fn style(&self) -> Style {
+        object::element_style(&self.header)
+    }
 
-        let mut new_elem = elem.to_owned();
-        new_elem.clear_attributes();
+    /// replace the `style` attribute of the layer, removing it if `style` is empty
+    pub fn set_style(&mut self, style: &Style) {
+        object::set_element_style(&mut self.header, style);
+    }
 
-        let atts = elem
-            .attributes()
-            .filter_map(Result::ok)
-            .filter(|att| att.key != QName(b"style"));
+    /// whether the layer is shown, which is the case unless its style has `display:none`
+    pub fn is_visible(&self) -> bool {
+        self.style().get("display") != Some("none")
+    }
 
-        new_elem.extend_attributes(atts);
-        self.header = Event::Start(new_elem);
+    /// make a layer visible
+    pub fn set_visible(&mut self) {
+        let mut style = self.style();
+        style.set("display", "inline");
+        self.set_style(&style);
     }
 
     /// make a layer hidden
     pub fn set_hidden(&mut self) {
-        let elem = if let Event::Start(elem) = &mut self.header {
-            elem
-        } else {
-            panic!("miss parsed a layer, the header should be Event::Start");
-        };
+        let mut style = self.style();
+        style.set("display", "none");
+        self.set_style(&style);
+    }
+
+    /// the `transform` attribute of the layer
+    pub fn transform(&self) -> Result<Transform, ParseTransformError> {
+        object::element_transform(&self.header)
+    }
+
+    /// the objects directly inside of this layer, including groups and sublayers
+    pub fn objects(&self) -> &[object::Object] {
+        &self.content
+    }
+
+    /// all sublayers (`<g inkscape:groupmode="layer">`) directly inside of this layer
+    pub fn sublayers(&self) -> impl Iterator<Item = &Layer> {
+        self.content.iter().filter_map(|object| match object {
+            object::Object::Layer(layer) => Some(layer),
+            _ => None,
+        })
+    }
+
+    /// all sublayers (`<g inkscape:groupmode="layer">`) directly inside of this layer
+    pub fn sublayers_mut(&mut self) -> impl Iterator<Item = &mut Layer> {
+        self.content.iter_mut().filter_map(|object| match object {
+            object::Object::Layer(layer) => Some(layer),
+            _ => None,
+        })
+    }
+
+    /// call `f` on the header of the layer and every xml element inside of it
+    pub(crate) fn for_each_element<'a, F>(&'a self, f: &mut F)
+    where
+        F: FnMut(&'a BytesStart<'static>),
+    {
+        f(&self.header);
+        object::for_each_element(&self.content, f);
+    }
+
+    /// call `f` on the header of the layer and every xml element inside of it,
+    /// reading the id and name of the layer again afterwards
+    pub(crate) fn for_each_element_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut BytesStart<'static>),
+    {
+        f(&mut self.header);
+        object::for_each_element_mut(&mut self.content, f);
+
+        if let Some(id) = object::attribute_value(&self.header, b"id") {
+            self.id = id;
+        }
+        if let Some(name) = object::attribute_value(&self.header, b"inkscape:label") {
+            self.name = name;
+        }
+    }
+
+    pub(crate) fn write<W: Write>(
+        self,
+        writer: &mut quick_xml::Writer<W>,
+    ) -> Result<(), LayerError> {
+        let header = Event::Start(self.header);
+        writer
+            .write_event(&header)
+            .map_err(|err| LayerHeader { err, header })?;
 
-        let mut new_elem = elem.to_owned();
-        new_elem.clear_attributes();
+        for object in self.content {
+            object.write(writer)?;
+        }
 
-        let atts = elem
-            .attributes()
-            .filter_map(Result::ok)
-            .filter(|att| att.key != QName(b"style"))
-            .chain(std::iter::once(quick_xml::events::attributes::Attribute {
-                key: QName(b"style"),
-                value: b"display:none".as_slice().into(),
-            }));
+        writer
+            .write_event(&self.footer)
+            .map_err(|err| LayerFooter::new(err, self.footer))?;
 
-        new_elem.extend_attributes(atts);
-        self.header = Event::Start(new_elem);
+        Ok(())
     }
 
     #[cfg(test)]
@@ -86,7 +199,7 @@ impl Layer {
         Self {
             id: "adf".into(),
             name: "adf".into(),
-            header: Event::Eof,
+            header: BytesStart::new("g"),
             content,
             footer: Event::Eof,
         }
@@ -106,26 +219,7 @@ impl Inkscape {
         }
 
         for layer in self.layers {
-            writer
-                .write_event(&layer.header)
-                .map_err(|err| LayerHeader {
-                    err,
-                    header: layer.header,
-                })
-                .map_err(LayerError::from)?;
-
-            for object in layer.content {
-                let event = object.into_event();
-                writer
-                    .write_event(&event)
-                    .map_err(|err| LayerBody { err, object: event })
-                    .map_err(LayerError::from)?;
-            }
-
-            writer
-                .write_event(&layer.footer)
-                .map_err(|err| LayerFooter::new(err, layer.footer))
-                .map_err(LayerError::from)?;
+            layer.write(&mut writer)?;
         }
 
         for event in self.trailing_events {
@@ -138,20 +232,36 @@ impl Inkscape {
     }
 
     pub fn parse_svg<R: BufRead>(reader: R, buffer: &mut Vec<u8>) -> Result<Self, Error> {
-        let mut reader = quick_xml::Reader::from_reader(reader);
+        let (inkscape, _warnings) = Self::parse_svg_with(reader, buffer, ParseOptions::default())?;
+        Ok(inkscape)
+    }
+
+    /// parse a document with `options`, returning a warning for every object that was kept
+    /// as plain xml instead of failing (see [`ParseOptions::lenient`])
+    pub fn parse_svg_with<R: BufRead>(
+        reader: R,
+        buffer: &mut Vec<u8>,
+        options: ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning>), Error> {
+        let mut reader = quick_xml::Reader::from_reader(parse::LineReader::new(reader));
+        let mut state = parse::ParseState::new(options);
 
-        let (leading_events, first_group) = parse::leading_events(&mut reader, buffer);
+        let (leading_events, first_group) = parse::leading_events(&mut reader, buffer, &mut state)
+            .map_err(|error| parse::error_at(error, &reader, &state))?;
 
         // read the inner layers
         let (layers, first_trailing) = if let Some(first_group) = first_group {
-            let (layers, first_trailing) = parse::layers(&mut reader, buffer, first_group)?;
+            let (layers, first_trailing) =
+                parse::layers(&mut reader, buffer, first_group, &mut state)
+                    .map_err(|error| parse::error_at(error, &reader, &state))?;
             (layers, Some(first_trailing))
         } else {
             (vec![], None)
         };
 
         let trailing_events = if let Some(first_trailing) = first_trailing {
-            parse::trailing_events(&mut reader, buffer, first_trailing)
+            parse::trailing_events(&mut reader, buffer, first_trailing, &mut state)
+                .map_err(|error| parse::error_at(error, &reader, &state))?
         } else {
             Vec::new()
         };
@@ -161,203 +271,2697 @@ impl Inkscape {
             layers,
             trailing_events,
         };
-        Ok(inkscape)
+        Ok((inkscape, state.warnings))
     }
 
+    /// replace a placeholder `<rect>` or the data of an `<image>` with `image`, writing it in
+    /// the form of href the document already uses (see [`Inkscape::href_attribute`])
     pub fn id_to_image(&mut self, id: &str, image: EncodedImage) -> Result<(), MissingId> {
-        for layer in &mut self.layers {
-            for object in layer.content.iter_mut() {
-                match object {
-                    object::Object::Rectangle(rect) => {
-                        if rect.ident.id == id {
-                            let image = rect.set_image(image);
-                            *object = object::Object::Image(image);
-
-                            return Ok(());
-                        }
-                    }
-                    object::Object::Image(img) => {
-                        if img.ident.id == id {
-                            img.update_image(image);
+        self.id_to_image_with(id, image, ImageOptions::default())
+    }
 
-                            return Ok(());
-                        }
-                    }
-                    object::Object::Other(_) => (),
-                };
+    /// replace a placeholder with `image`, choosing how it is written with `options`.
+    ///
+    /// A converted `<rect>` keeps only the attributes that mean the same thing on an `<image>`
+    /// (position, size, `transform`, `clip-path`, `opacity`, `inkscape:label`, ...) along with
+    /// its `<title>` and `<desc>`.
+    ///
+    /// An `<image>` keeps whichever of `xlink:href` and `href` it already has, so
+    /// `options.href` only decides the form for converted rectangles. The `xmlns:xlink`
+    /// namespace is declared on the root element if `xlink:href` is written and it is missing
+    pub fn id_to_image_with(
+        &mut self,
+        id: &str,
+        image: EncodedImage,
+        options: ImageOptions,
+    ) -> Result<(), MissingId> {
+        self.set_image_href(id, image.as_slice(), options)
+    }
+
+    /// replace a placeholder with `image`, resampled to the number of pixels the placeholder
+    /// covers at `resample.dpi` and encoded with `format`.
+    ///
+    /// The size of the placeholder is converted to physical units using the size and viewBox
+    /// of the root `<svg>` element, so a 4000px screenshot placed in a 30mm box at 300 dpi is
+    /// embedded with 354px across
+    pub fn id_to_image_resampled(
+        &mut self,
+        id: &str,
+        image: &image::DynamicImage,
+        format: image::ImageOutputFormat,
+        resample: Resample,
+    ) -> Result<(), ResampleError> {
+        let pixels = self.pixel_dimensions(id, resample.dpi)?;
+
+        let encoded = match resample.resize(image, pixels) {
+            Some(resized) => EncodedImage::from_dynamic_image(&resized, format)?,
+            None => EncodedImage::from_dynamic_image(image, format)?,
+        };
+
+        self.id_to_image(id, encoded)?;
+
+        Ok(())
+    }
+
+    /// replace a placeholder with `image` encoded with `format`, choosing how it is fit into
+    /// the placeholder when their aspect ratios differ.
+    ///
+    /// This sets `preserveAspectRatio` on the `<image>`, and for [`Fit::Cover`] and
+    /// [`Fit::KeepWidth`] crops the pixels or changes the height of the element so that every
+    /// renderer shows the same thing
+    pub fn id_to_image_fit(
+        &mut self,
+        id: &str,
+        image: &image::DynamicImage,
+        format: image::ImageOutputFormat,
+        fit: Fit,
+    ) -> Result<(), FitError> {
+        let (_, user_height) = self.dimensions(id)?;
+        // the placeholder as it is shown, which differs from its width and height when it
+        // (or a group it is in) is scaled differently in each direction
+        let (width, height) = self.shown_dimensions(id)?;
+
+        let cropped = match fit {
+            Fit::Cover(align) => fit::crop(image, (width, height), align),
+            Fit::Stretch | Fit::Contain(_) | Fit::KeepWidth => None,
+        };
+        let image = cropped.as_ref().unwrap_or(image);
+
+        self.id_to_image(id, EncodedImage::from_dynamic_image(image, format)?)?;
+
+        let object = self.object_mut(id)?;
+        object.set_attribute("preserveAspectRatio", &fit.preserve_aspect_ratio())?;
+
+        if fit == Fit::KeepWidth {
+            // `dimensions` found the object, so it has a geometry
+            if let Some(geometry) = object.geometry() {
+                let new_height =
+                    fit::keep_width_height(image, geometry.height, user_height, (width, height));
+                object.set_attribute("height", &new_height.to_string())?;
             }
         }
 
-        Err(MissingId::new(id.into()))
+        Ok(())
     }
 
-    pub fn dimensions(&mut self, id: &str) -> Result<(f64, f64), MissingId> {
-        for layer in &self.layers {
-            for object in &layer.content {
-                match object {
-                    object::Object::Rectangle(rect) => {
-                        if rect.ident.id == id {
-                            return Ok((rect.ident.width, rect.ident.height));
-                        }
-                    }
-                    object::Object::Image(img) => {
-                        if img.ident.id == id {
-                            return Ok((img.ident.width, img.ident.height));
-                        }
-                    }
-                    object::Object::Other(_) => (),
+    /// point a placeholder `<rect>` or `<image>` at an image file instead of embedding it.
+    ///
+    /// `path` is written into the document as it is, so it should be relative to where the
+    /// svg is saved (or absolute). Use [`Inkscape::embed_linked_images`] to embed the linked
+    /// files later on
+    pub fn id_to_linked_image<P: AsRef<Path>>(
+        &mut self,
+        id: &str,
+        path: P,
+    ) -> Result<(), MissingId> {
+        let link = object::path_to_link(path.as_ref());
+        let link = quick_xml::escape::escape(&link);
+        self.set_image_href(id, link.as_bytes(), ImageOptions::default())
+    }
+
+    /// embed every image that links to a file, reading the files relative to `base_dir`
+    /// (usually the directory of the svg), returning how many were embedded.
+    ///
+    /// If a relative link can not be found, the absolute `sodipodi:absref` path inkscape keeps
+    /// next to it is tried instead. Links to remote (`http://`, ...) resources and data URIs
+    /// are left alone.
+    ///
+    /// Every file is read before anything is embedded, so the document is left as it was if
+    /// one of them can not be read. Only `<image>`s in the layers are embedded, not the
+    /// images of [`ImageOptions::pattern_fill`] patterns
+    pub fn embed_linked_images<P: AsRef<Path>>(
+        &mut self,
+        base_dir: P,
+    ) -> Result<usize, EmbedLinkedImage> {
+        let base_dir = base_dir.as_ref();
+        let linked_path = |image: &Image| match image.href() {
+            Some(ImageHref::Linked(link)) => object::link_to_path(&link),
+            _ => None,
+        };
+
+        let mut encoded = Vec::new();
+        for layer in self.layers.iter_mut() {
+            object::try_for_each_image_mut(&mut layer.content, &mut |image| {
+                let path = match linked_path(image) {
+                    Some(path) => base_dir.join(path),
+                    None => return Ok(()),
                 };
-            }
+                let path = match image.absref() {
+                    Some(absref) if !path.exists() => PathBuf::from(absref),
+                    _ => path,
+                };
+
+                let image = EncodedImage::from_path(&path)
+                    .map_err(|error| EmbedLinkedImage::new(image.ident.id.clone(), error))?;
+                encoded.push(image);
+
+                Ok(())
+            })?;
+        }
+
+        let embedded = encoded.len();
+
+        // the images are visited in the same order as above
+        let mut encoded = encoded.into_iter();
+        for layer in self.layers.iter_mut() {
+            object::try_for_each_image_mut(&mut layer.content, &mut |image| {
+                if linked_path(image).is_some() {
+                    if let Some(encoded) = encoded.next() {
+                        image.set_href(encoded.as_slice(), HrefAttribute::default());
+                    }
+                }
+
+                Ok::<_, EmbedLinkedImage>(())
+            })?;
         }
 
-        Err(MissingId::new(id.into()))
+        Ok(embedded)
     }
 
-    pub fn object_ids(&self) -> IdIterator<'_> {
-        IdIterator::new(&self.layers)
+    /// write every embedded image to a file `{id}.{extension}` in `out_dir` and link to the
+    /// file instead, returning the paths of the files written. Characters of the id that
+    /// do not belong in a file name are replaced by `_`, and files that already exist are
+    /// never overwritten: `{id}-2.{extension}` (and so on) is used instead.
+    ///
+    /// Every image is decoded before anything is written, so the document is left as it
+    /// was if one of them can not be decoded. Only `<image>`s in the layers are extracted,
+    /// not the images of [`ImageOptions::pattern_fill`] patterns.
+    ///
+    /// Like [`Inkscape::id_to_linked_image`], `out_dir` is written into the links as it is,
+    /// so it should be relative to where the svg is saved
+    pub fn extract_embedded_images<P: AsRef<Path>>(
+        &mut self,
+        out_dir: P,
+    ) -> Result<Vec<PathBuf>, ExtractImageError> {
+        let out_dir = out_dir.as_ref();
+
+        let mut decoded = Vec::new();
+        for layer in self.layers.iter_mut() {
+            object::try_for_each_image_mut(&mut layer.content, &mut |image| {
+                if let Some(ImageHref::Embedded { mime, data }) = image.href() {
+                    let bytes = base64::decode(data)
+                        .map_err(|error| DecodeImage::new(image.ident.id.clone(), error))?;
+                    decoded.push((image.ident.id.clone(), mime, bytes));
+                }
+
+                Ok::<_, ExtractImageError>(())
+            })?;
+        }
+
+        if !decoded.is_empty() {
+            std::fs::create_dir_all(out_dir)
+                .map_err(|error| WriteFile::new(error, out_dir.to_owned()))?;
+        }
+
+        let mut file_names = HashSet::new();
+        let mut written = Vec::new();
+        for (id, mime, bytes) in decoded {
+            let stem = object::file_name(&id);
+            let extension = object::extension(&mime);
+
+            // two ids can end up as the same file name once they are sanitized
+            let file_name = (1..)
+                .map(|n| match n {
+                    1 => format!("{stem}.{extension}"),
+                    n => format!("{stem}-{n}.{extension}"),
+                })
+                .find(|name| !file_names.contains(name) && !out_dir.join(name).exists())
+                .unwrap();
+            file_names.insert(file_name.clone());
+
+            let path = out_dir.join(file_name);
+            std::fs::write(&path, bytes).map_err(|error| WriteFile::new(error, path.clone()))?;
+            written.push(path);
+        }
+
+        // the images are visited in the same order as above
+        let mut paths = written.iter();
+        for layer in self.layers.iter_mut() {
+            object::try_for_each_image_mut(&mut layer.content, &mut |image| {
+                if let Some(ImageHref::Embedded { .. }) = image.href() {
+                    if let Some(path) = paths.next() {
+                        let link = object::path_to_link(path);
+                        let link = quick_xml::escape::escape(&link);
+                        image.set_href(link.as_bytes(), HrefAttribute::default());
+                    }
+                }
+
+                Ok::<_, ExtractImageError>(())
+            })?;
+        }
+
+        Ok(written)
     }
 
-    pub fn get_layers(&self) -> &[Layer] {
-        &self.layers
+    /// the MIME type and bytes of the image file embedded in the `<image>` with id `id`, or
+    /// in the pattern a `<rect>` was filled with through [`ImageOptions::pattern_fill`].
+    /// See [`ImageData::to_image`] to decode its pixels
+    pub fn image_data(&self, id: &str) -> Result<ImageData, ImageDataError> {
+        let element = match self.object(id)? {
+            object @ Object::Image(_) => object.element(),
+            Object::Rectangle(rect) => rect
+                .fill_reference()
+                .and_then(|pattern| self.pattern_image(&pattern, id)),
+            _ => None,
+        }
+        .ok_or_else(|| NotImage::new(id.into()))?;
+
+        let href = object::attribute_value(element, b"xlink:href")
+            .or_else(|| object::attribute_value(element, b"href"))
+            .ok_or_else(|| NotEmbedded::new(id.into()))?;
+
+        ImageData::from_data_uri(id, &href)
     }
 
-    pub fn get_layers_mut(&mut self) -> &mut Vec<Layer> {
-        &mut self.layers
+    /// point a placeholder at `value`, which must already be escaped
+    fn set_image_href(
+        &mut self,
+        id: &str,
+        value: &[u8],
+        options: ImageOptions,
+    ) -> Result<(), MissingId> {
+        let href = options.href.unwrap_or_else(|| self.href_attribute());
+
+        if options.pattern_fill && self.fill_with_pattern(id, value, href) {
+            if href == HrefAttribute::XlinkHref {
+                self.declare_xlink_namespace();
+            }
+            return Ok(());
+        }
+
+        // generated up front, since the document can not be searched for used ids while
+        // the object is borrowed
+        let clip_id = options
+            .round_corners
+            .then(|| ids::IdGenerator::new(self.all_ids()).generate("clipPath"));
+        let mut corner_clip = None;
+
+        let object = self
+            .layers
+            .iter_mut()
+            .find_map(|layer| object::find_by_id_mut(&mut layer.content, id))
+            .ok_or_else(|| MissingId::new(id.into()))?;
+
+        match object {
+            object::Object::Rectangle(rect) => {
+                if options.round_corners {
+                    corner_clip = rect.corner_clip();
+                }
+                let image = rect.set_href(value, href);
+                *object = object::Object::Image(image);
+            }
+            object::Object::Image(img) => {
+                img.set_href(value, href);
+            }
+            // `find_by_id_mut` only returns objects with identifiers
+            object::Object::Text(_)
+            | object::Object::Layer(_)
+            | object::Object::Group(_)
+            | object::Object::Other(_) => unreachable!(),
+        };
+
+        let uses_xlink = object
+            .start_element()
+            .and_then(object::HrefAttribute::of_element)
+            == Some(HrefAttribute::XlinkHref);
+
+        if let (Some(mut clip), Some(clip_id), object::Object::Image(image)) =
+            (corner_clip, clip_id, object)
+        {
+            // an element can only have a single clip path, so one the placeholder already had
+            // clips the rounded rectangle instead
+            let mut style = object::element_style(&image.element);
+            let existing = object::attribute_value(&image.element, b"clip-path")
+                .or_else(|| style.remove("clip-path"));
+            if let Some(existing) = existing {
+                clip.push_attribute(("clip-path", existing.as_str()));
+            }
+            object::set_element_style(&mut image.element, &style);
+            object::set_attribute(
+                &mut image.element,
+                "clip-path",
+                Some(&format!("url(#{clip_id})")),
+            );
+
+            let mut clip_path = BytesStart::new("clipPath");
+            clip_path.push_attribute(("id", clip_id.as_str()));
+            clip_path.push_attribute(("clipPathUnits", "userSpaceOnUse"));
+            self.add_to_defs(vec![
+                Event::Start(clip_path),
+                Event::Empty(clip),
+                Event::End(BytesEnd::new("clipPath")),
+            ]);
+        }
+
+        if uses_xlink {
+            self.declare_xlink_namespace();
+        }
+
+        Ok(())
     }
-}
 
-pub struct IdIterator<'a> {
-    curr_group_idx: usize,
-    curr_group_object_idx: usize,
-    groups: &'a [Layer],
-}
+    /// fill the `<rect>` with id `id` with a pattern of the image at `value`, reusing the
+    /// pattern it is already filled with if it was filled this way before. Any other
+    /// pattern is left alone, since it may be shared with other elements. `false` if there
+    /// is no such rectangle
+    fn fill_with_pattern(&mut self, id: &str, value: &[u8], href: HrefAttribute) -> bool {
+        let rect = match self.object(id) {
+            Ok(object::Object::Rectangle(rect)) => rect.clone(),
+            _ => return false,
+        };
+        let image = rect.pattern_image(value, href);
 
-impl<'a> IdIterator<'a> {
-    pub fn new(groups: &'a [Layer]) -> IdIterator<'a> {
-        Self {
-            groups,
-            curr_group_idx: 0,
-            curr_group_object_idx: 0,
+        if let Some(pattern_id) = rect.fill_reference() {
+            if let Some(existing) = self.pattern_image_mut(&pattern_id, id) {
+                *existing = image;
+                return true;
+            }
         }
+
+        let pattern_id =
+            ids::IdGenerator::new(self.all_ids()).generate(&object::pattern_base_id(id));
+        self.add_to_defs(vec![
+            Event::Start(rect.pattern(&pattern_id)),
+            Event::Empty(image),
+            Event::End(BytesEnd::new("pattern")),
+        ]);
+
+        if let Some(object::Object::Rectangle(rect)) = self
+            .layers
+            .iter_mut()
+            .find_map(|layer| object::find_by_id_mut(&mut layer.content, id))
+        {
+            rect.set_fill_reference(&pattern_id);
+        }
+
+        true
     }
-}
 
-impl<'a> Iterator for IdIterator<'a> {
-    type Item = &'a str;
+    /// the `<image>` directly inside of the `<pattern>` with id `id` outside of the layers,
+    /// if that pattern was made to fill the placeholder with id `placeholder`
+    fn pattern_image(&self, id: &str, placeholder: &str) -> Option<&BytesStart<'static>> {
+        if !object::is_placeholder_pattern(id, placeholder) {
+            return None;
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // get a current group, or bubble up the `None`
-        let group = self.groups.get(self.curr_group_idx)?;
+        [&self.leading_events, &self.trailing_events]
+            .into_iter()
+            .find_map(|events| match &events[pattern_image_index(events, id)?] {
+                Event::Empty(element) => Some(element),
+                _ => None,
+            })
+    }
 
-        if let Some(group_object) = group.content.get(self.curr_group_object_idx) {
-            // match on the group object to see if this element is a rectangle or image,
-            // and therefore contains `Identifier` information we can return from the iterator
-            match group_object {
-                object::Object::Rectangle(rect) => {
-                    self.curr_group_object_idx += 1;
-                    Some(&rect.ident.id)
-                }
-                object::Object::Image(image) => {
-                    self.curr_group_object_idx += 1;
+    /// see [`Inkscape::pattern_image`]
+    fn pattern_image_mut(
+        &mut self,
+        id: &str,
+        placeholder: &str,
+    ) -> Option<&mut BytesStart<'static>> {
+        if !object::is_placeholder_pattern(id, placeholder) {
+            return None;
+        }
 
-                    Some(&image.ident.id)
-                }
-                object::Object::Other(_) => {
-                    // we HAVE a valid object, but since its not an object we normally care
-                    // about, we have not parsed the identifiers for it
-                    self.curr_group_object_idx += 1;
-                    self.next()
+        for events in [&mut self.leading_events, &mut self.trailing_events] {
+            if let Some(index) = pattern_image_index(events, id) {
+                if let Event::Empty(element) = &mut events[index] {
+                    return Some(element);
                 }
             }
-        } else {
-            // there are no more objects in this layer, go to the next layer
-            // group and return anything from there
-            self.curr_group_idx += 1;
-            self.curr_group_object_idx = 0;
+        }
+
+        None
+    }
+
+    /// add `events` to the end of the `<defs>` of the document, creating it at the start of
+    /// the root element if there is none
+    fn add_to_defs(&mut self, events: Vec<Event<'static>>) {
+        let events = match insert_into_defs(&mut self.leading_events, events) {
+            Ok(()) => return,
+            Err(events) => events,
+        };
+        let events = match insert_into_defs(&mut self.trailing_events, events) {
+            Ok(()) => return,
+            Err(events) => events,
+        };
+
+        let id = ids::IdGenerator::new(self.all_ids()).generate("defs");
+        let mut defs = BytesStart::new("defs");
+        defs.push_attribute(("id", id.as_str()));
+
+        let root = self.leading_events.iter().position(
+            |event| matches!(event, Event::Start(element) if element.name() == QName(b"svg")),
+        );
+        let index = root.map_or(self.leading_events.len(), |root| root + 1);
 
-            self.next()
+        let new = std::iter::once(Event::Start(defs))
+            .chain(events)
+            .chain(std::iter::once(Event::End(BytesEnd::new("defs"))));
+        self.leading_events.splice(index..index, new);
+    }
+
+    /// the form of href the document uses for images and references: the form of the first
+    /// element with an `xlink:href` or `href`, or `xlink:href` (as inkscape writes) if there
+    /// are none
+    pub fn href_attribute(&self) -> HrefAttribute {
+        let mut found = None;
+        let mut find = |element: &BytesStart<'static>| {
+            if found.is_none() {
+                found = HrefAttribute::of_element(element);
+            }
+        };
+
+        for event in self.leading_events.iter().chain(&self.trailing_events) {
+            object::event_element(event, &mut find);
+        }
+        for layer in &self.layers {
+            layer.for_each_element(&mut find);
         }
+
+        found.unwrap_or_default()
     }
-}
 
-#[test]
-fn id_iterator() {
-    use quick_xml::events::BytesStart;
+    /// add `xmlns:xlink` to the root element if it is not declared there
+    fn declare_xlink_namespace(&mut self) {
+        if let Some(root) = self.root_mut() {
+            if object::attribute_value(root, b"xmlns:xlink").is_none() {
+                root.push_attribute(("xmlns:xlink", object::XLINK_NAMESPACE));
+            }
+        }
+    }
 
-    use object::{Identifiers, Image, Object, Rectangle};
-    let groups = vec![
-        Layer::eof_group_test(vec![]),
-        Layer::eof_group_test(vec![
-            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("1"))),
-            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("2"))),
-            Object::Image(Image::from_ident(Identifiers::zeros_with_id("3"))),
-        ]),
-        Layer::eof_group_test(vec![]),
-        Layer::eof_group_test(vec![
-            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("4"))),
-            Object::Other(Event::Empty(BytesStart::new("doesnt_matter"))),
-            Object::Other(Event::Empty(BytesStart::new("doesnt_matter2"))),
-            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("5"))),
-        ]),
-        Layer::eof_group_test(vec![]),
-    ];
+    /// replace a placeholder `<rect>` or `<image>` with the vector content of another svg
+    /// document, scaled to fit inside of the placeholder.
+    ///
+    /// Every id inside of `svg` is prefixed with `{id}-` so that it does not collide with the
+    /// ids already in this document. The new element keeps the placeholder's `id`, but is no
+    /// longer a rectangle or image, so it can not be replaced a second time.
+    pub fn id_to_svg(
+        &mut self,
+        id: &str,
+        svg: SvgDocument,
+        placement: SvgPlacement,
+    ) -> Result<(), EmbedSvgError> {
+        let viewport = self.viewport()?;
 
-    let iter = IdIterator::new(&groups);
-    let ids = iter.collect::<Vec<_>>();
-    assert_eq!(&["1", "2", "3", "4", "5"], ids.as_slice());
-}
+        let object = self
+            .layers
+            .iter_mut()
+            .find_map(|layer| object::find_by_id_mut(&mut layer.content, id))
+            .ok_or_else(|| MissingId::new(id.into()))?;
 
-#[test]
-fn make_layer_hidden() {
-    use std::path::PathBuf;
+        let new_object = match object {
+            object::Object::Rectangle(object::Rectangle { ident, element, .. })
+            | object::Object::Image(object::Image { ident, element, .. }) => {
+                svg.into_object(element, ident, &viewport, placement)?
+            }
+            // `find_by_id_mut` only returns objects with identifiers
+            object::Object::Text(_)
+            | object::Object::Layer(_)
+            | object::Object::Group(_)
+            | object::Object::Other(_) => unreachable!(),
+        };
 
-    let path = PathBuf::from("./static/three_layer_hidding.svg");
-    let output = PathBuf::from("./static/three_layer_hidding_output.svg");
+        *object = new_object;
 
-    let file = std::fs::File::open(&path).unwrap();
-    let writer = std::fs::File::create(&output).unwrap();
+        Ok(())
+    }
 
-    let reader = std::io::BufReader::new(file);
+    /// the characters of a `<text>`, with a `\n` between every line
+    pub fn get_text(&self, id: &str) -> Result<String, TextError> {
+        match self.object(id)? {
+            object::Object::Text(text) => Ok(text.text()),
+            _ => Err(NotText::new(id.into()).into()),
+        }
+    }
 
-    let mut buffer = Vec::new();
+    /// replace the characters of a `<text>`, keeping the position and styling of its lines.
+    ///
+    /// Every line of `text` goes into one `<tspan sodipodi:role="line">`. Lines that are no
+    /// longer needed are removed, and extra lines are added as copies of the last line
+    /// (with new ids), moved down by the line spacing of the text
+    pub fn set_text(&mut self, id: &str, text: &str) -> Result<(), TextError> {
+        let mut ids = ids::IdGenerator::new(self.all_ids());
 
-    let mut inkscape = Inkscape::parse_svg(reader, &mut buffer).unwrap();
+        match self.object_mut(id)? {
+            object::Object::Text(object) => {
+                object.set_text(text, &mut ids);
+                Ok(())
+            }
+            _ => Err(NotText::new(id.into()).into()),
+        }
+    }
 
-    //dbg!(&inkscape);
+    /// replace every `{{name}}` placeholder in the document with its value in `values`,
+    /// in character data (such as the lines of a `<text>`) and in attribute values (such as
+    /// `inkscape:label` or `xlink:href`). Whitespace around the name is ignored.
+    ///
+    /// A placeholder has to be written in a single piece of character data, so one that
+    /// inkscape split over several `<tspan>`s because part of it is styled differently will
+    /// not be found
+    pub fn fill_template(&mut self, values: &HashMap<String, String>) -> TemplateReport {
+        let mut substitution = template::Substitution::new(values);
 
-    for layer in inkscape.get_layers_mut().iter_mut() {
-        dbg!(layer.id(), layer.name());
-        if layer.name() == "Layer 2" {
-            println!("setting layer 2 hidden");
-            layer.set_hidden();
+        for event in self
+            .leading_events
+            .iter_mut()
+            .chain(self.trailing_events.iter_mut())
+        {
+            substitution.event(event);
         }
 
-        if layer.name() == "Layer 3" {
-            println!("setting layer 3 visible");
-            layer.set_visible();
+        for layer in self.layers.iter_mut() {
+            layer.for_each_element_mut(&mut |element| substitution.element(element));
+            object::for_each_event_mut(&mut layer.content, &mut |event| {
+                // elements have already been filled in above
+                if let Event::Text(_) | Event::CData(_) = event {
+                    substitution.event(event)
+                }
+            });
+        }
+
+        substitution.report()
+    }
+
+    /// the width and height of a rectangle or image in the user units of the document
+    pub fn dimensions(&mut self, id: &str) -> Result<(f64, f64), DimensionError> {
+        self.user_dimensions(id)
+    }
+
+    /// see [`Inkscape::dimensions`]
+    fn user_dimensions(&self, id: &str) -> Result<(f64, f64), DimensionError> {
+        let ident = self.identifiers(id)?;
+
+        // plain numbers are already in user units, so there is no need to look at the
+        // root element (which may not have a size at all)
+        if !ident.has_units() {
+            return Ok((ident.width.value, ident.height.value));
         }
+
+        let viewport = self.viewport()?;
+        Ok(ident.user_dimensions(&viewport))
     }
 
-    inkscape.write_svg(writer).unwrap();
-}
+    /// the width and height of a rectangle or image converted to `unit`, using the
+    /// size and viewBox of the root `<svg>` element
+    pub fn dimensions_in(&self, id: &str, unit: Unit) -> Result<(f64, f64), DimensionError> {
+        let ident = self.identifiers(id)?;
+        let viewport = self.viewport()?;
 
-#[test]
-fn many_layers_parse() {
-    let path = "./static/julia_python_share_cxx.svg";
-    let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
-    let mut buffer = Vec::new();
-    let inkscape = Inkscape::parse_svg(reader, &mut buffer).unwrap();
-    let layer_names = inkscape
-        .get_layers()
-        .iter()
-        .map(|layer| layer.name().to_string())
-        .collect::<Vec<String>>();
+        let (width, height) = ident.user_dimensions(&viewport);
 
-    assert!(layer_names.contains(&"pythoncode".to_string()));
-    assert!(layer_names.contains(&"sharedcode".to_string()));
-    assert!(layer_names.contains(&"juliacode".to_string()));
-    assert!(layer_names.contains(&"julia_bindings".to_string()));
-    assert!(layer_names.contains(&"python_bindings".to_string()));
+        Ok((
+            viewport.from_user_units(width, unit, Axis::Horizontal),
+            viewport.from_user_units(height, unit, Axis::Vertical),
+        ))
+    }
+
+    /// the number of pixels along the width and height of a rectangle or image when the
+    /// document is rendered at `dpi`, which includes the scale of the transforms of the
+    /// object and its groups. Rotating the object does not change them
+    pub fn pixel_dimensions(&self, id: &str, dpi: f64) -> Result<(f64, f64), DimensionError> {
+        let (width, height) = self.shown_dimensions(id)?;
+        let viewport = self.viewport()?;
+
+        Ok((
+            viewport.user_units_to_pixels(width, dpi, Axis::Horizontal),
+            viewport.user_units_to_pixels(height, dpi, Axis::Vertical),
+        ))
+    }
+
+    /// the parsed `style` attribute of a rectangle or image
+    pub fn object_style(&self, id: &str) -> Result<Style, MissingId> {
+        self.layers
+            .iter()
+            .find_map(|layer| object::find_by_id(&layer.content, id))
+            .and_then(object::Object::element)
+            .map(object::element_style)
+            .ok_or_else(|| MissingId::new(id.into()))
+    }
+
+    /// replace the `style` attribute of a rectangle or image, removing it if `style` is empty
+    pub fn set_object_style(&mut self, id: &str, style: &Style) -> Result<(), MissingId> {
+        let element = self
+            .layers
+            .iter_mut()
+            .find_map(|layer| object::find_by_id_mut(&mut layer.content, id))
+            .and_then(object::Object::element_mut)
+            .ok_or_else(|| MissingId::new(id.into()))?;
+
+        object::set_element_style(element, style);
+
+        Ok(())
+    }
+
+    /// the bounding box of a rectangle or image in the user units of the document, after
+    /// applying its own `transform` and the transforms of every layer and group it is in
+    pub fn bounding_box(&self, id: &str) -> Result<BoundingBox, DimensionError> {
+        let (rect, transform) = self.placement(id)?;
+
+        Ok(transform.bounding_box(rect.x, rect.y, rect.width, rect.height))
+    }
+
+    /// the width and height of a rectangle or image in the user units of the document,
+    /// scaled by its own `transform` and the transforms of every layer and group it is in
+    fn shown_dimensions(&self, id: &str) -> Result<(f64, f64), DimensionError> {
+        let (rect, transform) = self.placement(id)?;
+        let (scale_x, scale_y) = transform.axis_scale();
+
+        Ok((rect.width * scale_x, rect.height * scale_y))
+    }
+
+    /// the position and size of a rectangle or image in user units, before it is transformed,
+    /// along with the transform that places it in the document
+    fn placement(&self, id: &str) -> Result<(BoundingBox, Transform), DimensionError> {
+        let (object, parent) = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let transform = layer.transform()?;
+                object::find_by_id_with_transform(&layer.content, id, transform)
+            })
+            .find_map(Result::transpose)
+            .transpose()?
+            .ok_or_else(|| MissingId::new(id.into()))?;
+
+        // `find_by_id_with_transform` only returns objects with identifiers
+        let ident = object.ident().unwrap();
+
+        let (x, y, width, height) = if ident.has_units() {
+            let viewport = self.viewport()?;
+            let (x, y) = ident.user_position(&viewport);
+            let (width, height) = ident.user_dimensions(&viewport);
+            (x, y, width, height)
+        } else {
+            (
+                ident.x.value,
+                ident.y.value,
+                ident.width.value,
+                ident.height.value,
+            )
+        };
+
+        let rect = BoundingBox {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        Ok((rect, parent.then(&ident.transform)))
+    }
+
+    /// how the user units of the document map to physical units, from the root `<svg>` element
+    pub fn viewport(&self) -> Result<Viewport, ViewportError> {
+        let root = self.root().ok_or(MissingSvgRoot)?;
+
+        Viewport::from_root(root)
+    }
+
+    /// the root `<svg>` element
+    fn root(&self) -> Option<&BytesStart<'static>> {
+        self.leading_events.iter().find_map(|event| match event {
+            Event::Start(element) if element.name() == QName(b"svg") => Some(element),
+            _ => None,
+        })
+    }
+
+    fn root_mut(&mut self) -> Option<&mut BytesStart<'static>> {
+        self.leading_events
+            .iter_mut()
+            .find_map(|event| match event {
+                Event::Start(element) if element.name() == QName(b"svg") => Some(element),
+                _ => None,
+            })
+    }
+
+    fn identifiers(&self, id: &str) -> Result<&object::Identifiers, MissingId> {
+        self.layers
+            .iter()
+            .find_map(|layer| object::find_by_id(&layer.content, id))
+            .and_then(object::Object::ident)
+            .ok_or_else(|| MissingId::new(id.into()))
+    }
+
+    /// add an empty layer named `name` above all other layers, returning it
+    pub fn add_layer(&mut self, name: &str) -> &mut Layer {
+        self.insert_layer(self.layers.len(), name)
+    }
+
+    /// add an empty layer named `name` at `index` in the z-order, where `0` is the bottom
+    /// layer and an `index` past the top layer puts it on top. The layer is given an id that
+    /// is not used anywhere else in the document
+    pub fn insert_layer(&mut self, index: usize, name: &str) -> &mut Layer {
+        if self.layers.is_empty() {
+            self.split_root_end();
+        }
+
+        let index = index.min(self.layers.len());
+
+        let id = ids::IdGenerator::new(self.all_ids()).generate("layer");
+        self.layers.insert(index, Layer::new(id, name.to_string()));
+
+        &mut self.layers[index]
+    }
+
+    /// remove a layer (and everything in it) from the document, returning it
+    pub fn remove_layer(&mut self, id: &str) -> Result<Layer, MissingLayer> {
+        let index = self.layer_index(id)?;
+        Ok(self.layers.remove(index))
+    }
+
+    /// remove the top level layer named `name` (and everything in it) from the document,
+    /// returning it
+    pub fn remove_layer_by_name(&mut self, name: &str) -> Result<Layer, LayerLookupError> {
+        let indices = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.name == name)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        match indices.as_slice() {
+            [index] => Ok(self.layers.remove(*index)),
+            [] => Err(MissingLayer::new(name.into()).into()),
+            indices => {
+                let ids = indices
+                    .iter()
+                    .map(|index| self.layers[*index].id.clone())
+                    .collect();
+                Err(AmbiguousLayer::new(name.into(), ids).into())
+            }
+        }
+    }
+
+    /// change the name of a layer shown in inkscape (its `inkscape:label`)
+    pub fn rename_layer(&mut self, id: &str, name: &str) -> Result<(), MissingLayer> {
+        let index = self.layer_index(id)?;
+        self.layers[index].set_name(name);
+
+        Ok(())
+    }
+
+    /// copy a layer and everything in it, placing the copy directly above the original.
+    ///
+    /// The copy is named `{name} copy`, and every element in it is given a new id the same
+    /// way inkscape does when duplicating. References between elements inside of the
+    /// layer (`href="#id"`, `url(#id)`, and `#id` in `<style>` elements) are updated to point
+    /// at the copies
+    pub fn duplicate_layer(&mut self, id: &str) -> Result<&mut Layer, MissingLayer> {
+        let index = self.layer_index(id)?;
+
+        let mut copy = self.layers[index].clone();
+
+        let mut generator = ids::IdGenerator::new(self.all_ids());
+        let mut renames = HashMap::new();
+        copy.for_each_element(&mut |element| {
+            if let Some(id) = object::attribute_value(element, b"id") {
+                let new_id = generator.generate(&id);
+                renames.insert(id, new_id);
+            }
+        });
+
+        copy.for_each_element_mut(&mut |element| ids::rename_in_element(element, &renames));
+        let mut in_style = false;
+        object::for_each_event_mut(&mut copy.content, &mut |event| {
+            ids::rename_in_style(event, &mut in_style, &renames, None)
+        });
+        copy.set_name(&format!("{} copy", copy.name));
+
+        self.layers.insert(index + 1, copy);
+
+        Ok(&mut self.layers[index + 1])
+    }
+
+    /// move a layer one step up in the z-order, so that it is drawn over the layer that
+    /// was above it. The top layer stays where it is
+    pub fn move_layer_up(&mut self, id: &str) -> Result<(), MissingLayer> {
+        let index = self.layer_index(id)?;
+
+        if index + 1 < self.layers.len() {
+            self.layers.swap(index, index + 1);
+        }
+
+        Ok(())
+    }
+
+    /// move a layer one step down in the z-order, so that it is drawn under the layer that
+    /// was below it. The bottom layer stays where it is
+    pub fn move_layer_down(&mut self, id: &str) -> Result<(), MissingLayer> {
+        let index = self.layer_index(id)?;
+
+        if index > 0 {
+            self.layers.swap(index, index - 1);
+        }
+
+        Ok(())
+    }
+
+    /// the layer or sublayer with the id `id`
+    pub fn layer_by_id(&self, id: &str) -> Result<&Layer, LayerLookupError> {
+        self.find_layer(id, |layer| layer.id == id)
+    }
+
+    /// the layer or sublayer with the id `id`
+    pub fn layer_by_id_mut(&mut self, id: &str) -> Result<&mut Layer, LayerLookupError> {
+        self.find_layer_mut(id, |layer| layer.id == id)
+    }
+
+    /// the layer or sublayer named `name` in inkscape (its `inkscape:label`). Since labels
+    /// do not have to be unique, this fails if more than one layer has the name
+    pub fn layer_by_name(&self, name: &str) -> Result<&Layer, LayerLookupError> {
+        self.find_layer(name, |layer| layer.name == name)
+    }
+
+    /// the layer or sublayer named `name` in inkscape (its `inkscape:label`). Since labels
+    /// do not have to be unique, this fails if more than one layer has the name
+    pub fn layer_by_name_mut(&mut self, name: &str) -> Result<&mut Layer, LayerLookupError> {
+        self.find_layer_mut(name, |layer| layer.name == name)
+    }
+
+    /// show every top level layer named in `names` and hide all others.
+    ///
+    /// Nothing is changed if one of the names does not belong to a top level layer
+    pub fn set_only_visible(&mut self, names: &[&str]) -> Result<(), MissingLayer> {
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !self.layers.iter().any(|layer| layer.name == **name))
+        {
+            return Err(MissingLayer::new(missing.to_string()));
+        }
+
+        for layer in self.layers.iter_mut() {
+            if names.contains(&layer.name.as_str()) {
+                layer.set_visible();
+            } else {
+                layer.set_hidden();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the only layer or sublayer that `matches`, where `key` is the name or id being looked up
+    fn find_layer<F>(&self, key: &str, matches: F) -> Result<&Layer, LayerLookupError>
+    where
+        F: Fn(&Layer) -> bool,
+    {
+        let mut found = Vec::new();
+        matching_layers(self.layers.iter(), &matches, &mut found);
+
+        match found.as_slice() {
+            [layer] => Ok(layer),
+            [] => Err(MissingLayer::new(key.into()).into()),
+            layers => {
+                let ids = layers.iter().map(|layer| layer.id.clone()).collect();
+                Err(AmbiguousLayer::new(key.into(), ids).into())
+            }
+        }
+    }
+
+    /// the only layer or sublayer that `matches`, where `key` is the name or id being looked up
+    fn find_layer_mut<F>(&mut self, key: &str, matches: F) -> Result<&mut Layer, LayerLookupError>
+    where
+        F: Fn(&Layer) -> bool,
+    {
+        self.find_layer(key, &matches)?;
+
+        // `find_layer` has checked that there is exactly one match
+        Ok(first_matching_layer_mut(self.layers.iter_mut(), &matches).unwrap())
+    }
+
+    /// the position of a top level layer
+    fn layer_index(&self, id: &str) -> Result<usize, MissingLayer> {
+        self.layers
+            .iter()
+            .position(|layer| layer.id == id)
+            .ok_or_else(|| MissingLayer::new(id.into()))
+    }
+
+    /// every id used anywhere in the document
+    fn all_ids(&self) -> HashSet<String> {
+        let mut ids = HashSet::new();
+        let mut add = |element: &BytesStart<'static>| {
+            if let Some(id) = object::attribute_value(element, b"id") {
+                ids.insert(id);
+            }
+        };
+
+        for event in self.leading_events.iter().chain(&self.trailing_events) {
+            object::event_element(event, &mut add);
+        }
+
+        for layer in &self.layers {
+            layer.for_each_element(&mut add);
+        }
+
+        ids
+    }
+
+    /// a document without layers is parsed entirely into `leading_events`, so move the
+    /// closing `</svg>` (and anything after it) to `trailing_events` to make room for layers
+    fn split_root_end(&mut self) {
+        let root_end = self
+            .leading_events
+            .iter()
+            .rposition(|event| matches!(event, Event::End(end) if end.name() == QName(b"svg")));
+
+        if let Some(root_end) = root_end {
+            let mut trailing = self.leading_events.split_off(root_end);
+            trailing.append(&mut self.trailing_events);
+            self.trailing_events = trailing;
+        }
+    }
+
+    /// every object in the document along with the (innermost) layer it is in, depth first
+    pub fn objects(&self) -> ObjectIterator<'_> {
+        ObjectIterator::new(&self.layers)
+    }
+
+    /// every object picked out by `selector` along with the layer it is in, depth first
+    pub fn objects_matching<'a>(
+        &'a self,
+        selector: &'a Selector,
+    ) -> impl Iterator<Item = (&'a Layer, &'a Object)> {
+        self.objects()
+            .filter(move |(_, object)| selector.matches(object))
+    }
+
+    /// every layer and object matching `query`, depth first. The handles can be passed to
+    /// methods taking an id with [`Handle::id`]
+    pub fn select(&self, query: &Query) -> Vec<Handle> {
+        query.select(&self.layers)
+    }
+
+    /// call `f` on every layer and object matching `query`, depth first, returning their
+    /// handles.
+    ///
+    /// The matches are found before any of them are changed, so changing one object does not
+    /// change which others match
+    pub fn select_mut<F>(&mut self, query: &Query, mut f: F) -> Vec<Handle>
+    where
+        F: FnMut(SelectedMut<'_>),
+    {
+        let handles = query.select(&self.layers);
+
+        for handle in &handles {
+            if let Some(selected) = query::resolve_mut(&mut self.layers, handle) {
+                f(selected);
+            }
+        }
+
+        handles
+    }
+
+    /// the id of the single object picked out by `selector`, to use with methods that take
+    /// an id such as [`Inkscape::id_to_image`] or [`Inkscape::dimensions`]
+    pub fn select_id(&self, selector: &Selector) -> Result<String, SelectError> {
+        let ids = self
+            .objects_matching(selector)
+            .filter_map(|(_, object)| object.id())
+            .collect();
+
+        single_match(selector, ids)
+    }
+
+    /// the id of the single rectangle or image picked out by `selector`
+    fn select_placeholder(&self, selector: &Selector) -> Result<String, SelectError> {
+        single_match(selector, self.placeholders_matching(selector))
+    }
+
+    /// the ids of every rectangle and image picked out by `selector`, each of them once
+    fn placeholders_matching(&self, selector: &Selector) -> Vec<String> {
+        let mut seen = HashSet::new();
+
+        self.objects_matching(selector)
+            .filter_map(|(_, object)| object.ident().map(|ident| ident.id.clone()))
+            .filter(|id| seen.insert(id.clone()))
+            .collect()
+    }
+
+    /// replace the single rectangle or image picked out by `selector` with `image`, see
+    /// [`Inkscape::id_to_image`]. Returns the id of the placeholder
+    pub fn selector_to_image(
+        &mut self,
+        selector: &Selector,
+        image: EncodedImage,
+    ) -> Result<String, SelectImageError> {
+        let id = self.select_placeholder(selector)?;
+        self.id_to_image(&id, image)?;
+
+        Ok(id)
+    }
+
+    /// the width and height of the single rectangle or image picked out by `selector`, see
+    /// [`Inkscape::dimensions`]
+    pub fn selector_dimensions(&self, selector: &Selector) -> Result<(f64, f64), DimensionError> {
+        let id = self.select_placeholder(selector)?;
+        self.user_dimensions(&id)
+    }
+
+    /// fill every rectangle or image picked out by each selector with its image, reporting
+    /// which selectors matched no rectangle or image
+    pub fn fill_matching<I>(&mut self, images: I) -> FillReport
+    where
+        I: IntoIterator<Item = (Selector, EncodedImage)>,
+    {
+        let mut report = FillReport::default();
+
+        for (selector, image) in images {
+            let ids = self.placeholders_matching(&selector);
+
+            if ids.is_empty() {
+                report.unmatched.push(selector);
+                continue;
+            }
+
+            for id in ids {
+                match self.id_to_image(&id, image.clone()) {
+                    Ok(()) => report.filled.push(id),
+                    Err(error) => report.failed.push(error),
+                }
+            }
+        }
+
+        report
+    }
+
+    /// the object with the id `id`, which may be of any kind
+    pub fn object(&self, id: &str) -> Result<&Object, MissingId> {
+        self.layers
+            .iter()
+            .find_map(|layer| object::find_object(&layer.content, id))
+            .ok_or_else(|| MissingId::new(id.into()))
+    }
+
+    /// the object with the id `id`, which may be of any kind
+    pub fn object_mut(&mut self, id: &str) -> Result<&mut Object, MissingId> {
+        self.layers
+            .iter_mut()
+            .find_map(|layer| object::find_object_mut(&mut layer.content, id))
+            .ok_or_else(|| MissingId::new(id.into()))
+    }
+
+    pub fn object_ids(&self) -> IdIterator<'_> {
+        IdIterator::new(&self.layers)
+    }
+
+    pub fn get_layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn get_layers_mut(&mut self) -> &mut Vec<Layer> {
+        &mut self.layers
+    }
+}
+
+/// the single id in `ids` that `selector` picked out
+fn single_match(selector: &Selector, ids: Vec<String>) -> Result<String, SelectError> {
+    match ids.as_slice() {
+        [] => Err(NoMatch::new(selector.to_string()).into()),
+        [id] => Ok(id.clone()),
+        _ => Err(AmbiguousMatch::new(selector.to_string(), ids).into()),
+    }
+}
+
+/// the index of the `<image>` directly inside of the `<pattern>` with id `id` in `events`
+fn pattern_image_index(events: &[Event<'static>], id: &str) -> Option<usize> {
+    let start = events.iter().position(|event| match event {
+        Event::Start(element) => {
+            element.name() == QName(b"pattern")
+                && object::attribute_value(element, b"id").as_deref() == Some(id)
+        }
+        _ => false,
+    })?;
+
+    events[start + 1..]
+        .iter()
+        .take_while(|event| !matches!(event, Event::End(end) if end.name() == QName(b"pattern")))
+        .position(
+            |event| matches!(event, Event::Empty(element) if element.name() == QName(b"image")),
+        )
+        .map(|index| start + 1 + index)
+}
+
+/// insert `new` at the end of the first `<defs>` in `events`, expanding `<defs/>` if it is
+/// empty. `new` is given back if there is no `<defs>`
+fn insert_into_defs(
+    events: &mut Vec<Event<'static>>,
+    new: Vec<Event<'static>>,
+) -> Result<(), Vec<Event<'static>>> {
+    let is_defs = |element: &BytesStart<'_>| element.name() == QName(b"defs");
+
+    let start = events.iter().position(|event| match event {
+        Event::Start(element) | Event::Empty(element) => is_defs(element),
+        _ => false,
+    });
+    let start = match start {
+        Some(start) => start,
+        None => return Err(new),
+    };
+
+    if let Event::Empty(element) = &events[start] {
+        let element = element.clone();
+        let new = std::iter::once(Event::Start(element))
+            .chain(new)
+            .chain(std::iter::once(Event::End(BytesEnd::new("defs"))));
+        events.splice(start..=start, new);
+        return Ok(());
+    }
+
+    // the closing tag matching the start of the defs
+    let mut depth: usize = 0;
+    let end = events[start + 1..].iter().position(|event| match event {
+        Event::Start(_) => {
+            depth += 1;
+            false
+        }
+        Event::End(_) if depth == 0 => true,
+        Event::End(_) => {
+            depth -= 1;
+            false
+        }
+        _ => false,
+    });
+
+    match end {
+        Some(end) => {
+            let end = start + 1 + end;
+            events.splice(end..end, new);
+            Ok(())
+        }
+        // the defs are not closed in these events (they contain a layer), so they can not be
+        // added to here
+        None => Err(new),
+    }
+}
+
+/// collect every layer in `layers` that `matches`, along with matching sublayers, depth first
+fn matching_layers<'a, I, F>(layers: I, matches: &F, found: &mut Vec<&'a Layer>)
+where
+    I: Iterator<Item = &'a Layer>,
+    F: Fn(&Layer) -> bool,
+{
+    for layer in layers {
+        if matches(layer) {
+            found.push(layer);
+        }
+
+        matching_layers(layer.sublayers(), matches, found);
+    }
+}
+
+/// the first layer in `layers` (or their sublayers) that `matches`, depth first
+fn first_matching_layer_mut<'a, I, F>(layers: I, matches: &F) -> Option<&'a mut Layer>
+where
+    I: Iterator<Item = &'a mut Layer>,
+    F: Fn(&Layer) -> bool,
+{
+    for layer in layers {
+        if matches(layer) {
+            return Some(layer);
+        }
+
+        if let Some(found) = first_matching_layer_mut(layer.sublayers_mut(), matches) {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Iterates over every object in the document along with the layer it is in,
+/// including the objects nested inside of groups and sublayers
+pub struct ObjectIterator<'a> {
+    layers: std::slice::Iter<'a, Layer>,
+    /// the objects of every group or layer we are currently descended into, along with
+    /// the layer they belong to and how deep we are inside of raw xml elements in them,
+    /// with the innermost group at the end
+    stack: Vec<(&'a Layer, std::slice::Iter<'a, Object>, usize)>,
+}
+
+impl<'a> ObjectIterator<'a> {
+    fn new(layers: &'a [Layer]) -> Self {
+        Self {
+            layers: layers.iter(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for ObjectIterator<'a> {
+    type Item = (&'a Layer, &'a Object);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (layer, objects, depth) =
+                if let Some((layer, objects, depth)) = self.stack.last_mut() {
+                    (*layer, objects, depth)
+                } else {
+                    let layer = self.layers.next()?;
+                    self.stack.push((layer, layer.content.iter(), 0));
+                    continue;
+                };
+
+            let object = match objects.next() {
+                Some(object) => object,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            // raw xml inside of raw xml elements (such as the `<title>` of a `<circle>`)
+            // belongs to that element
+            let nested = *depth > 0;
+            *depth = depth.saturating_add_signed(object.raw_nesting());
+            if nested && matches!(object, Object::Other(_)) {
+                continue;
+            }
+
+            match object {
+                Object::Layer(sublayer) => self.stack.push((sublayer, sublayer.content.iter(), 0)),
+                Object::Group(group) => self.stack.push((layer, group.content.iter(), 0)),
+                _ => (),
+            }
+
+            // whitespace, comments and closing tags are not objects of their own
+            if object.start_element().is_some() {
+                return Some((layer, object));
+            }
+        }
+    }
+}
+
+/// Iterates over the ids of every rectangle and image in the document,
+/// including the ones nested inside of groups and sublayers
+pub struct IdIterator<'a> {
+    layers: std::slice::Iter<'a, Layer>,
+    /// the objects of every group we are currently descended into,
+    /// with the innermost group at the end
+    stack: Vec<std::slice::Iter<'a, object::Object>>,
+}
+
+impl<'a> IdIterator<'a> {
+    pub fn new(groups: &'a [Layer]) -> IdIterator<'a> {
+        Self {
+            layers: groups.iter(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for IdIterator<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let objects = if let Some(objects) = self.stack.last_mut() {
+                objects
+            } else {
+                // we have finished with the current layer, move to the next one
+                // or bubble up the `None`
+                let layer = self.layers.next()?;
+                self.stack.push(layer.content.iter());
+                continue;
+            };
+
+            // match on the group object to see if this element is a rectangle or image,
+            // and therefore contains `Identifier` information we can return from the iterator
+            match objects.next() {
+                Some(object::Object::Rectangle(rect)) => return Some(&rect.ident.id),
+                Some(object::Object::Image(image)) => return Some(&image.ident.id),
+                Some(object::Object::Layer(layer)) => self.stack.push(layer.content.iter()),
+                Some(object::Object::Group(group)) => self.stack.push(group.content.iter()),
+                // we HAVE a valid object, but since its not an object we normally care
+                // about, we have not parsed the identifiers for it
+                Some(object::Object::Text(_) | object::Object::Other(_)) => (),
+                // there are no more objects in this group, go back up to the parent
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// the `xmlns:xlink` declaration, for the root attributes of [`test_document`]
+#[cfg(test)]
+const XLINK_ATTRIBUTE: &str = r#" xmlns:xlink="http://www.w3.org/1999/xlink""#;
+
+/// an A4 page in millimetres, for the root attributes of [`test_document`]
+#[cfg(test)]
+const A4_ATTRIBUTES: &str = r#" width="210mm" height="297mm" viewBox="0 0 210 297""#;
+
+/// parse a document whose root `<svg>` element declares the inkscape namespace along with
+/// `root_attributes`, and contains `body`
+#[cfg(test)]
+fn test_document(root_attributes: &str, body: &str) -> Inkscape {
+    let svg = format!(
+        r#"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"{root_attributes}>{body}</svg>"#
+    );
+
+    let mut buffer = Vec::new();
+    Inkscape::parse_svg(svg.as_bytes(), &mut buffer).unwrap()
+}
+
+/// parse a document with a single layer `Layer 1` (`layer1`) holding `content`, which may
+/// use `xlink:href`
+#[cfg(test)]
+fn test_layer(content: &str) -> Inkscape {
+    test_document(
+        XLINK_ATTRIBUTE,
+        &format!(
+            r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">{content}</g>"#
+        ),
+    )
+}
+
+/// parse one of the documents in `./static`
+#[cfg(test)]
+fn test_file(name: &str) -> Inkscape {
+    let path = PathBuf::from("./static").join(name);
+    let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+    let mut buffer = Vec::new();
+    Inkscape::parse_svg(reader, &mut buffer).unwrap()
+}
+
+#[test]
+fn id_iterator() {
+    use quick_xml::events::BytesStart;
+
+    use object::{Identifiers, Image, Object, Rectangle};
+    let groups = vec![
+        Layer::eof_group_test(vec![]),
+        Layer::eof_group_test(vec![
+            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("1"))),
+            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("2"))),
+            Object::Image(Image::from_ident(Identifiers::zeros_with_id("3"))),
+        ]),
+        Layer::eof_group_test(vec![]),
+        Layer::eof_group_test(vec![
+            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("4"))),
+            Object::raw(Event::Empty(BytesStart::new("doesnt_matter"))),
+            Object::raw(Event::Empty(BytesStart::new("doesnt_matter2"))),
+            Object::Rectangle(Rectangle::from_ident(Identifiers::zeros_with_id("5"))),
+        ]),
+        Layer::eof_group_test(vec![]),
+    ];
+
+    let iter = IdIterator::new(&groups);
+    let ids = iter.collect::<Vec<_>>();
+    assert_eq!(&["1", "2", "3", "4", "5"], ids.as_slice());
+}
+
+#[test]
+fn make_layer_hidden() {
+    use std::path::PathBuf;
+
+    let path = PathBuf::from("./static/three_layer_hidding.svg");
+    let output = PathBuf::from("./static/three_layer_hidding_output.svg");
+
+    let file = std::fs::File::open(&path).unwrap();
+    let writer = std::fs::File::create(&output).unwrap();
+
+    let reader = std::io::BufReader::new(file);
+
+    let mut buffer = Vec::new();
+
+    let mut inkscape = Inkscape::parse_svg(reader, &mut buffer).unwrap();
+
+    //dbg!(&inkscape);
+
+    for layer in inkscape.get_layers_mut().iter_mut() {
+        dbg!(layer.id(), layer.name());
+        if layer.name() == "Layer 2" {
+            println!("setting layer 2 hidden");
+            layer.set_hidden();
+        }
+
+        if layer.name() == "Layer 3" {
+            println!("setting layer 3 visible");
+            layer.set_visible();
+        }
+    }
+
+    inkscape.write_svg(writer).unwrap();
+}
+
+#[test]
+fn many_layers_parse() {
+    let path = "./static/julia_python_share_cxx.svg";
+    let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    let mut buffer = Vec::new();
+    let inkscape = Inkscape::parse_svg(reader, &mut buffer).unwrap();
+    let layer_names = inkscape
+        .get_layers()
+        .iter()
+        .map(|layer| layer.name().to_string())
+        .collect::<Vec<String>>();
+
+    assert!(layer_names.contains(&"pythoncode".to_string()));
+    assert!(layer_names.contains(&"sharedcode".to_string()));
+    assert!(layer_names.contains(&"juliacode".to_string()));
+    assert!(layer_names.contains(&"julia_bindings".to_string()));
+    assert!(layer_names.contains(&"python_bindings".to_string()));
+}
+
+#[test]
+fn nested_groups_parse() {
+    let mut inkscape = test_file("nested_groups.svg");
+
+    // a plain group should not end the layer, so both layers are still at the top level
+    let layer_names = inkscape
+        .get_layers()
+        .iter()
+        .map(|layer| layer.name().to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(layer_names, ["panels", "background"]);
+
+    let sublayers = inkscape.get_layers()[0]
+        .sublayers()
+        .map(|layer| layer.name())
+        .collect::<Vec<_>>();
+    assert_eq!(sublayers, ["captions"]);
+
+    let ids = inkscape.object_ids().collect::<Vec<_>>();
+    assert_eq!(ids, ["rect100", "rect110", "rect140", "rect150", "rect160"]);
+
+    assert_eq!(inkscape.dimensions("rect110").unwrap(), (30.0, 20.0));
+
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image("rect110", image).unwrap();
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+
+    // the written document should parse back to the same tree
+    let mut buffer = Vec::new();
+    let inkscape = Inkscape::parse_svg(output.as_slice(), &mut buffer).unwrap();
+    let ids = inkscape.object_ids().collect::<Vec<_>>();
+    assert_eq!(ids, ["rect100", "rect110", "rect140", "rect150", "rect160"]);
+    assert_eq!(inkscape.get_layers()[0].sublayers().count(), 1);
+}
+
+#[test]
+fn rect_with_children() {
+    let mut inkscape = test_layer(
+        r##"<rect
+       style="fill:#ff0000"
+       id="rect286"
+       width="85.292282"
+       height="48.174355"
+       x="38.076923"
+       y="16.923077"><title
+         id="title300">Panel A</title><desc
+         id="desc302">placeholder for the first panel</desc></rect>"##,
+    );
+
+    let ids = inkscape.object_ids().collect::<Vec<_>>();
+    assert_eq!(ids, ["rect286"]);
+
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image("rect286", image).unwrap();
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // the children should have been kept, and the closing tag renamed with the element
+    assert!(output.contains("<title\n         id=\"title300\">Panel A</title>"));
+    assert!(output.contains("placeholder for the first panel</desc></image></g>"));
+    assert!(!output.contains("</rect>"));
+}
+
+#[test]
+fn rect_to_svg() {
+    let plot = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 200 100">
+ <defs><clipPath id="clip1"><rect x="0" y="0" width="200" height="100"/></clipPath></defs>
+ <path id="line" d="M 0 0 L 200 100" clip-path="url(#clip1)"/>
+</svg>"##;
+
+    for placement in [SvgPlacement::Transform, SvgPlacement::NestedSvg] {
+        let mut inkscape = test_file("nested_groups.svg");
+
+        let svg = SvgDocument::from_bytes(plot.as_bytes()).unwrap();
+        inkscape.id_to_svg("rect100", svg, placement).unwrap();
+
+        // the placeholder is no longer a rectangle
+        let ids = inkscape.object_ids().collect::<Vec<_>>();
+        assert_eq!(ids, ["rect110", "rect140", "rect150", "rect160"]);
+
+        let mut output = Vec::new();
+        inkscape.write_svg(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(r#"<clipPath id="rect100-clip1">"#));
+        assert!(output.contains(r#"clip-path="url(#rect100-clip1)""#));
+
+        match placement {
+            // the 50 x 40 placeholder at (10, 10) holds a 200 x 100 plot at a scale of 0.25,
+            // leaving (40 - 25) / 2 of vertical space on either side
+            SvgPlacement::Transform => {
+                assert!(output
+                    .contains(r#"<g id="rect100" transform="translate(10,17.5) scale(0.25)""#))
+            }
+            SvgPlacement::NestedSvg => assert!(output
+                .contains(r#"<svg x="10" y="10" width="50" height="40" viewBox="0 0 200 100""#)),
+        }
+
+        // the output should still parse
+        let mut buffer = Vec::new();
+        Inkscape::parse_svg(output.as_bytes(), &mut buffer).unwrap();
+    }
+}
+
+#[test]
+fn unit_dimensions() {
+    // the page is 210mm wide, but only 105 user units, so one user unit is 2mm
+    let mut inkscape = test_document(
+        r#" width="210mm" height="297mm" viewBox="0 0 105 148.5""#,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="10mm" height="1in" x="0" y="0" />
+    <rect id="rect2" width="20" height="50%" x="0" y="0" />
+  </g>"#,
+    );
+
+    let close = |(a, b): (f64, f64), (c, d): (f64, f64)| {
+        assert!(
+            (a - c).abs() < 1e-9 && (b - d).abs() < 1e-9,
+            "{a},{b} != {c},{d}"
+        );
+    };
+
+    close(inkscape.dimensions("rect1").unwrap(), (5.0, 12.7));
+    close(inkscape.dimensions("rect2").unwrap(), (20.0, 74.25));
+
+    close(
+        inkscape.dimensions_in("rect1", Unit::Mm).unwrap(),
+        (10.0, 25.4),
+    );
+    close(
+        inkscape.dimensions_in("rect2", Unit::In).unwrap(),
+        (40.0 / 25.4, 148.5 / 25.4),
+    );
+    close(
+        inkscape.pixel_dimensions("rect1", 254.0).unwrap(),
+        (100.0, 254.0),
+    );
+    close(
+        inkscape.dimensions_in("rect2", Unit::Percent).unwrap(),
+        (20.0 / 105.0 * 100.0, 50.0),
+    );
+}
+
+#[test]
+fn transformed_bounding_box() {
+    let inkscape = test_file("julia_python_share_cxx.svg");
+
+    // image393 has transform="matrix(0.77134943,0,0,0.77134943,205.87103,-24.982763)"
+    let bbox = inkscape.bounding_box("image393").unwrap();
+    let close = |a: f64, b: f64| assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    close(bbox.x, 0.77134943 * 42.634773 + 205.87103);
+    close(bbox.y, 0.77134943 * 68.12043 - 24.982763);
+    close(bbox.width, 0.77134943 * 227.02031);
+    close(bbox.height, 0.77134943 * 202.4614);
+
+    // transforms of the layer and group around the rectangle are applied too
+    let inkscape = test_document(
+        r#" width="100" height="100""#,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1" transform="translate(100,0)">
+    <g id="g1" transform="scale(2)">
+      <rect id="rect1" width="10" height="5" x="1" y="2" transform="rotate(90)" />
+    </g>
+  </g>"#,
+    );
+    let bbox = inkscape.bounding_box("rect1").unwrap();
+
+    // rotating maps (1, 2, 10, 5) onto x in [-7, -2], y in [1, 11], which is then
+    // scaled by 2 and moved 100 to the right
+    close(bbox.x, 86.0);
+    close(bbox.y, 2.0);
+    close(bbox.width, 10.0);
+    close(bbox.height, 20.0);
+
+    // along its own width and height, the rotated and scaled rectangle is 20 by 10 pixels
+    // at 96 dpi
+    let (width, height) = inkscape.pixel_dimensions("rect1", 96.0).unwrap();
+    close(width, 20.0);
+    close(height, 10.0);
+
+    // rotating does not change the number of pixels, although the bounding box grows
+    let inkscape = test_document(
+        r#" width="100" height="100""#,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="96" height="96" transform="rotate(45)" />
+  </g>"#,
+    );
+    close(
+        inkscape.bounding_box("rect1").unwrap().width,
+        96.0 * 2f64.sqrt(),
+    );
+    let (width, height) = inkscape.pixel_dimensions("rect1", 96.0).unwrap();
+    close(width, 96.0);
+    close(height, 96.0);
+}
+
+#[test]
+fn visibility_keeps_style() {
+    let mut inkscape = test_document(
+        "",
+        r##"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1"
+     style="opacity:0.5;display:none;mix-blend-mode:multiply">
+    <rect style="fill:#ff0000;stroke-width:0.665001" id="rect286" width="85.292282" height="48.174355" />
+  </g>
+  <g inkscape:label="Layer 2" inkscape:groupmode="layer" id="layer2"></g>"##,
+    );
+
+    let layers = inkscape.get_layers_mut();
+    assert!(!layers[0].is_visible());
+    assert!(layers[1].is_visible());
+
+    layers[0].set_visible();
+    assert_eq!(
+        layers[0].style().to_string(),
+        "opacity:0.5;display:inline;mix-blend-mode:multiply"
+    );
+
+    layers[1].set_hidden();
+    assert_eq!(layers[1].style().to_string(), "display:none");
+
+    let mut style = inkscape.object_style("rect286").unwrap();
+    style.set("fill", "#00ff00");
+    style.set("opacity", "0.25");
+    inkscape.set_object_style("rect286", &style).unwrap();
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains(r#"style="opacity:0.5;display:inline;mix-blend-mode:multiply">"#));
+    assert!(output.contains(r#"id="layer2" style="display:none">"#));
+    assert!(output.contains(r#"style="fill:#00ff00;stroke-width:0.665001;opacity:0.25""#));
+}
+
+#[test]
+fn manage_layers() {
+    let mut inkscape = test_file("nested_groups.svg");
+
+    let names = |inkscape: &Inkscape| {
+        inkscape
+            .get_layers()
+            .iter()
+            .map(|layer| layer.name().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&inkscape), ["panels", "background"]);
+
+    // layer1, layer2 and layer3 are already taken
+    let id = inkscape.add_layer("annotations").id().to_string();
+    assert_eq!(id, "layer4");
+
+    inkscape.rename_layer("layer3", "backdrop").unwrap();
+    inkscape.move_layer_down("layer3").unwrap();
+    inkscape.move_layer_down("layer3").unwrap();
+    assert_eq!(names(&inkscape), ["backdrop", "panels", "annotations"]);
+
+    let copy = inkscape.duplicate_layer("layer1").unwrap();
+    assert_eq!(copy.name(), "panels copy");
+    assert_ne!(copy.id(), "layer1");
+    assert_eq!(copy.sublayers().next().unwrap().name(), "captions");
+    assert_eq!(
+        names(&inkscape),
+        ["backdrop", "panels", "panels copy", "annotations"]
+    );
+
+    // every object of the copy has a new id
+    let ids = inkscape.object_ids().collect::<Vec<_>>();
+    let unique = ids.iter().collect::<HashSet<_>>();
+    assert_eq!(ids.len(), 9);
+    assert_eq!(unique.len(), 9);
+
+    inkscape.remove_layer_by_name("annotations").unwrap();
+    assert!(inkscape.remove_layer("layer4").is_err());
+    assert!(inkscape.move_layer_up("not_a_layer").is_err());
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+
+    let mut buffer = Vec::new();
+    let inkscape = Inkscape::parse_svg(output.as_slice(), &mut buffer).unwrap();
+    assert_eq!(names(&inkscape), ["backdrop", "panels", "panels copy"]);
+    assert_eq!(inkscape.object_ids().count(), 9);
+
+    // a document without any layers
+    let mut inkscape = test_document("", "");
+    inkscape.add_layer("Layer 1");
+    // an index past the top layer puts the layer on top
+    inkscape.insert_layer(5, "Layer 2");
+    assert_eq!(names(&inkscape), ["Layer 1", "Layer 2"]);
+    inkscape.remove_layer_by_name("Layer 2").unwrap();
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.ends_with(
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1"></g></svg>"#
+    ));
+}
+
+#[test]
+fn duplicate_layer_references() {
+    let mut inkscape = test_layer(
+        r##"<style>#rect1 { fill: url(#grad1) }</style>
+    <linearGradient id="grad1" />
+    <rect id="rect1" width="10" height="10" style="fill:url(&quot;#grad1&quot;)" />
+    <path id="a&amp;b" />
+    <use id="use1" xlink:href="#a&amp;b" />"##,
+    );
+
+    inkscape.duplicate_layer("layer1").unwrap();
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // the original is left alone
+    assert!(output.contains("<style>#rect1 { fill: url(#grad1) }</style>"));
+    assert!(output.contains("<style>#rect2 { fill: url(#grad2) }</style>"));
+    assert!(output
+        .contains(r#"id="rect2" width="10" height="10" style="fill:url(&quot;#grad2&quot;)""#));
+    assert!(output.contains(r#"<path id="a&amp;b1"/>"#));
+    assert!(output.contains(r##"<use id="use2" xlink:href="#a&amp;b1"/>"##));
+}
+
+#[test]
+fn find_layers() {
+    let mut inkscape = test_file("nested_groups.svg");
+
+    assert_eq!(inkscape.layer_by_name("panels").unwrap().id(), "layer1");
+    // sublayers are found too
+    assert_eq!(inkscape.layer_by_name("captions").unwrap().id(), "layer2");
+    assert_eq!(inkscape.layer_by_id("layer3").unwrap().name(), "background");
+
+    assert!(matches!(
+        inkscape.layer_by_name("missing"),
+        Err(LayerLookupError::MissingLayer(_))
+    ));
+
+    inkscape
+        .layer_by_id_mut("layer2")
+        .unwrap()
+        .set_name("panels");
+    match inkscape.layer_by_name_mut("panels") {
+        Err(LayerLookupError::AmbiguousLayer(error)) => assert_eq!(error.ids, ["layer1", "layer2"]),
+        other => panic!("expected an ambiguous layer, got {other:?}"),
+    }
+
+    inkscape.set_only_visible(&["background"]).unwrap();
+    assert!(!inkscape.layer_by_id("layer1").unwrap().is_visible());
+    assert!(inkscape.layer_by_id("layer3").unwrap().is_visible());
+
+    // an unknown name leaves every layer as it was
+    assert!(inkscape.set_only_visible(&["panels", "missing"]).is_err());
+    assert!(!inkscape.layer_by_id("layer1").unwrap().is_visible());
+}
+
+#[test]
+fn lenient_parse() {
+    let svg = r##"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"><g
+     inkscape:label="Layer 1"
+     inkscape:groupmode="layer"
+     id="layer1"><rect
+       style="fill:#cccccc"
+       width="100"
+       height="10" /><rect
+       id="rect2"
+       width="10em"
+       height="10"><title>decoration</title></rect><rect
+       id="rect3"
+       width="20"
+       height="30" /></g></svg>"##;
+
+    let mut buffer = Vec::new();
+    match Inkscape::parse_svg(svg.as_bytes(), &mut buffer) {
+        Err(Error::ParseLayer(error)) => {
+            assert!(matches!(*error.error, ParseLayer::ParseObject(_)))
+        }
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+
+    let mut buffer = Vec::new();
+    let (inkscape, warnings) =
+        Inkscape::parse_svg_with(svg.as_bytes(), &mut buffer, ParseOptions::lenient()).unwrap();
+
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings
+        .iter()
+        .all(|warning| warning.layer_name() == "Layer 1"));
+    assert_eq!(inkscape.object_ids().collect::<Vec<_>>(), ["rect3"]);
+
+    // the decorative rectangles are written back out unchanged
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), svg);
+}
+
+#[test]
+fn unparseable_position() {
+    let svg = r##"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+  <g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="10" height="10" x="calc(1px)" transform="skew(3)" />
+  </g>
+</svg>"##;
+
+    // the position and transform fall back to their defaults without failing the parse
+    let mut buffer = Vec::new();
+    let (mut inkscape, warnings) =
+        Inkscape::parse_svg_with(svg.as_bytes(), &mut buffer, ParseOptions::default()).unwrap();
+
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].position.line, 3);
+    assert_eq!(
+        inkscape.object("rect1").unwrap().geometry(),
+        Some(Geometry {
+            x: Length::user(0.0),
+            y: Length::user(0.0),
+            width: Length::user(10.0),
+            height: Length::user(10.0),
+            transform: Transform::IDENTITY,
+        })
+    );
+
+    // and the object can still be edited and filled
+    let object = inkscape.object_mut("rect1").unwrap();
+    object.set_attribute("class", "panel").unwrap();
+    object.remove_attribute("class").unwrap();
+    assert_eq!(object.geometry().unwrap().x, Length::user(0.0));
+
+    let square = image::DynamicImage::new_rgb8(10, 10);
+    inkscape
+        .id_to_image_fit(
+            "rect1",
+            &square,
+            image::ImageOutputFormat::Png,
+            Fit::Stretch,
+        )
+        .unwrap();
+    assert_eq!(inkscape.object("rect1").unwrap().kind(), ObjectKind::Image);
+}
+
+#[test]
+fn parse_error_position() {
+    // the second rectangle is missing its height
+    let svg = r##"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+  <g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="10" height="10" />
+    <rect id="rect2" width="10" />
+  </g>
+</svg>"##;
+
+    let mut buffer = Vec::new();
+    match Inkscape::parse_svg(svg.as_bytes(), &mut buffer) {
+        Err(Error::ParseLayer(error)) => {
+            assert!(matches!(*error.error, ParseLayer::ParseObject(_)));
+            assert_eq!(error.position.line, 4);
+            assert_eq!(error.position.column, 5);
+            assert_eq!(
+                error.position.offset,
+                svg.find("<rect id=\"rect2\"").unwrap()
+            );
+        }
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+
+    // a stray closing tag between the layers is an error instead of a panic
+    let svg = r##"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+  <g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1"></g>
+  </text>
+</svg>"##;
+
+    let mut buffer = Vec::new();
+    match Inkscape::parse_svg(svg.as_bytes(), &mut buffer) {
+        Err(Error::ParseLayer(error)) => assert_eq!(error.position.line, 3),
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn reader_errors_are_not_truncated() {
+    let layer = r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">"#;
+
+    let phase = |svg: &str| {
+        let mut buffer = Vec::new();
+        match Inkscape::parse_svg(svg.as_bytes(), &mut buffer) {
+            Err(Error::ParseLayer(error)) => match *error.error {
+                ParseLayer::ReadXml(read) => read.phase,
+                other => panic!("expected a reader error, got {other:?}"),
+            },
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    };
+
+    assert_eq!(
+        phase(r#"<svg><defs><linearGradient></defs></svg>"#),
+        ParsePhase::Leading
+    );
+    assert_eq!(
+        phase(&format!(
+            r#"<svg>{layer}<rect id="r" width="1" height="1"></g></svg>"#
+        )),
+        ParsePhase::Layers
+    );
+    assert_eq!(
+        phase(&format!(r#"<svg>{layer}</g></text></svg>"#)),
+        ParsePhase::Layers
+    );
+    assert_eq!(
+        phase(&format!(r#"<svg>{layer}</g><metadata></title></svg>"#)),
+        ParsePhase::Trailing
+    );
+}
+
+#[test]
+fn typed_objects() {
+    let mut inkscape = test_file("nested_groups.svg");
+
+    let objects = inkscape
+        .objects()
+        .map(|(layer, object)| (layer.name(), object.kind(), object.id().unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        objects,
+        [
+            ("panels", ObjectKind::Group, "g120".to_string()),
+            ("panels", ObjectKind::Rectangle, "rect100".to_string()),
+            ("panels", ObjectKind::Group, "g130".to_string()),
+            ("panels", ObjectKind::Rectangle, "rect110".to_string()),
+            ("panels", ObjectKind::Rectangle, "rect140".to_string()),
+            ("panels", ObjectKind::Layer, "layer2".to_string()),
+            ("captions", ObjectKind::Rectangle, "rect150".to_string()),
+            ("background", ObjectKind::Rectangle, "rect160".to_string()),
+        ]
+    );
+
+    let rect = inkscape.object("rect110").unwrap();
+    assert_eq!(
+        rect.geometry(),
+        Some(Geometry {
+            x: Length::user(70.0),
+            y: Length::user(10.0),
+            width: Length::user(30.0),
+            height: Length::user(20.0),
+            transform: Transform::IDENTITY,
+        })
+    );
+    assert_eq!(
+        rect.attributes()[0],
+        ("style".into(), "fill:#00ff00".into())
+    );
+    assert_eq!(
+        inkscape.object("g130").unwrap().children().unwrap().len(),
+        3
+    );
+
+    let rect = inkscape.object_mut("rect110").unwrap();
+    rect.set_attribute("width", "35").unwrap();
+    rect.set_attribute("inkscape:label", "Panel B").unwrap();
+    // a rectangle must keep a valid size
+    assert!(rect.set_attribute("height", "tall").is_err());
+    assert!(rect.remove_attribute("id").is_err());
+    assert_eq!(rect.label().as_deref(), Some("Panel B"));
+    assert_eq!(inkscape.dimensions("rect110").unwrap(), (35.0, 20.0));
+
+    inkscape
+        .object_mut("layer2")
+        .unwrap()
+        .set_attribute("inkscape:label", "notes")
+        .unwrap();
+    assert!(inkscape.layer_by_name("notes").is_ok());
+
+    // a layer must keep its id and name
+    let layer = inkscape.object_mut("layer2").unwrap();
+    assert!(layer.remove_attribute("id").is_err());
+    assert!(layer.set_attribute("inkscape:label", "").is_err());
+    assert!(layer.remove_attribute("inkscape:label").is_err());
+    assert_eq!(layer.label().as_deref(), Some("notes"));
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(r#"width="35""#));
+    assert!(output.contains(r#"inkscape:label="Panel B""#));
+
+    let inkscape = test_file("julia_python_share_cxx.svg");
+
+    match inkscape.object("image393").unwrap().image_href() {
+        Some(ImageHref::Embedded { mime, data }) => {
+            assert_eq!(mime, "image/png");
+            assert!(base64::decode(data).is_ok());
+        }
+        other => panic!("expected an embedded image, got {other:?}"),
+    }
+    assert!(inkscape
+        .objects()
+        .any(|(_, object)| object.kind() == ObjectKind::Text));
+}
+
+#[test]
+fn edit_text() {
+    let mut inkscape = test_file("julia_python_share_cxx.svg");
+
+    let (id, original) = inkscape
+        .objects()
+        .find_map(|(_, object)| match object {
+            Object::Text(text) => Some((object.id().unwrap(), text.text())),
+            _ => None,
+        })
+        .unwrap();
+    assert!(!original.is_empty());
+
+    inkscape.set_text(&id, "n = 42").unwrap();
+    assert_eq!(inkscape.get_text(&id).unwrap(), "n = 42");
+
+    assert!(matches!(
+        inkscape.get_text("image393"),
+        Err(TextError::NotText(_))
+    ));
+    assert!(matches!(
+        inkscape.set_text("missing", ""),
+        Err(TextError::MissingId(_))
+    ));
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+
+    let mut buffer = Vec::new();
+    let inkscape = Inkscape::parse_svg(output.as_slice(), &mut buffer).unwrap();
+    assert_eq!(inkscape.get_text(&id).unwrap(), "n = 42");
+}
+
+#[test]
+fn fill_template() {
+    let mut inkscape = test_document(
+        XLINK_ATTRIBUTE,
+        r#"<title>{{ title }}</title>
+  <g inkscape:label="{{sample_name}}" inkscape:groupmode="layer" id="layer1">
+    <text id="text1"><tspan id="tspan1">{{sample_name}}, {{date}}</tspan></text>
+    <image id="image1" width="10" height="10" xlink:href="{{plot}}" />
+  </g>"#,
+    );
+
+    let values = [
+        ("title", "Figure 1"),
+        ("sample_name", "WT <control>"),
+        ("plot", "plots/figure_1.png"),
+        ("unused", "value"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .into();
+
+    let report = inkscape.fill_template(&values);
+    assert_eq!(report.unresolved, ["date"]);
+    assert_eq!(report.unused, ["unused"]);
+    assert!(!report.is_complete());
+
+    assert_eq!(
+        inkscape.get_text("text1").unwrap(),
+        "WT <control>, {{date}}"
+    );
+    assert!(inkscape.layer_by_name("WT <control>").is_ok());
+    assert_eq!(
+        inkscape.object("image1").unwrap().image_href(),
+        Some(ImageHref::Linked("plots/figure_1.png".into()))
+    );
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("<title>Figure 1</title>"));
+    assert!(output.contains(r#"inkscape:label="WT &lt;control&gt;""#));
+}
+
+#[test]
+fn svg2_href() {
+    let mut inkscape = test_document(
+        r#" xmlns="http://www.w3.org/2000/svg""#,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <image id="image1" width="10" height="10" href="old.png" />
+    <rect id="rect1" width="10" height="10" />
+    <rect id="rect2" width="10" height="10" />
+  </g>"#,
+    );
+    assert_eq!(inkscape.href_attribute(), HrefAttribute::Href);
+
+    let image = || EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image("image1", image()).unwrap();
+    inkscape.id_to_image("rect1", image()).unwrap();
+    inkscape
+        .id_to_image_with(
+            "rect2",
+            image(),
+            ImageOptions::default().with_href(HrefAttribute::XlinkHref),
+        )
+        .unwrap();
+
+    let image1 = inkscape.object("image1").unwrap();
+    assert!(image1.attribute("xlink:href").is_none());
+    assert!(matches!(
+        image1.image_href(),
+        Some(ImageHref::Embedded { .. })
+    ));
+    assert!(inkscape
+        .object("rect1")
+        .unwrap()
+        .attribute("xlink:href")
+        .is_none());
+    assert!(inkscape
+        .object("rect2")
+        .unwrap()
+        .attribute("href")
+        .is_none());
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("xmlns:xlink=").count(), 1);
+    assert!(output.contains(r#"xmlns:xlink="http://www.w3.org/1999/xlink""#));
+}
+
+#[test]
+fn link_and_embed_images() {
+    let red = std::fs::canonicalize("./static/10x10_red.png").unwrap();
+    let mut inkscape = test_layer(&format!(
+        r#"<rect id="rect1" width="10" height="10" />
+    <image id="image2" width="10" height="10" xlink:href="moved/red.png" sodipodi:absref="{}" />
+    <image id="image3" width="10" height="10" xlink:href="https://example.com/plot.png" />
+    <image id="image4" width="10" height="10" xlink:href="data:image/svg+xml;utf8,&lt;svg/&gt;" />"#,
+        red.display()
+    ));
+
+    inkscape
+        .id_to_linked_image("rect1", "static/10x10_green.png")
+        .unwrap();
+    assert_eq!(
+        inkscape.object("rect1").unwrap().image_href(),
+        Some(ImageHref::Linked("static/10x10_green.png".into()))
+    );
+
+    // the moved image is found through its absolute path, and the remote one and the data URI
+    // are left alone
+    assert_eq!(inkscape.embed_linked_images(".").unwrap(), 2);
+    let image2 = inkscape.object("image2").unwrap();
+    assert!(image2.attribute("sodipodi:absref").is_none());
+    for id in ["rect1", "image2"] {
+        assert!(matches!(
+            inkscape.object(id).unwrap().image_href(),
+            Some(ImageHref::Embedded { .. })
+        ));
+    }
+    assert!(matches!(
+        inkscape.object("image3").unwrap().image_href(),
+        Some(ImageHref::Linked(_))
+    ));
+
+    let out_dir = std::env::temp_dir().join(format!("inkscape-extract-{}", std::process::id()));
+    let written = inkscape.extract_embedded_images(&out_dir).unwrap();
+    assert_eq!(
+        written,
+        [out_dir.join("rect1.png"), out_dir.join("image2.png")]
+    );
+    assert_eq!(
+        std::fs::read(&written[1]).unwrap(),
+        std::fs::read(&red).unwrap()
+    );
+    assert_eq!(
+        inkscape.object("image2").unwrap().image_href(),
+        Some(ImageHref::Linked(object::path_to_link(&written[1])))
+    );
+
+    // files that are already there are left alone
+    let mut inkscape = test_layer(
+        r#"<rect id="image2" width="10" height="10" />
+    <rect id="image2-2" width="10" height="10" />"#,
+    );
+    for id in ["image2", "image2-2"] {
+        let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+        inkscape.id_to_image(id, image).unwrap();
+    }
+    let written = inkscape.extract_embedded_images(&out_dir).unwrap();
+    assert_eq!(
+        written,
+        [out_dir.join("image2-2.png"), out_dir.join("image2-2-2.png")]
+    );
+    assert_eq!(
+        std::fs::read(out_dir.join("image2.png")).unwrap(),
+        std::fs::read(&red).unwrap()
+    );
+
+    std::fs::remove_dir_all(out_dir).unwrap();
+
+    // nothing is embedded when one of the files is missing
+    let mut inkscape = test_layer(
+        r#"<image id="image1" width="10" height="10" xlink:href="static/10x10_green.png" />
+    <image id="image2" width="10" height="10" xlink:href="static/missing.png" />"#,
+    );
+    assert!(inkscape.embed_linked_images(".").is_err());
+    assert_eq!(
+        inkscape.object("image1").unwrap().image_href(),
+        Some(ImageHref::Linked("static/10x10_green.png".into()))
+    );
+}
+
+#[test]
+fn decode_image_data() {
+    let mut inkscape = test_layer(
+        r#"<rect id="rect1" width="10" height="10" />
+    <image id="linked" width="10" height="10" xlink:href="plot.png" />
+    <image id="utf8" width="10" height="10" xlink:href="data:image/svg+xml;utf8,&lt;svg/&gt;" />
+    <image id="truncated" width="10" height="10" xlink:href="data:image/png;base64" />
+    <image id="invalid" width="10" height="10" xlink:href="data:image/png;base64,@@@@" />"#,
+    );
+
+    assert!(matches!(
+        inkscape.image_data("rect1"),
+        Err(ImageDataError::NotImage(_))
+    ));
+    assert!(matches!(
+        inkscape.image_data("linked"),
+        Err(ImageDataError::NotEmbedded(_))
+    ));
+    assert!(matches!(
+        inkscape.image_data("utf8"),
+        Err(ImageDataError::UnsupportedEncoding(_))
+    ));
+    assert!(matches!(
+        inkscape.image_data("truncated"),
+        Err(ImageDataError::MalformedDataUri(_))
+    ));
+    assert!(matches!(
+        inkscape.image_data("invalid"),
+        Err(ImageDataError::DecodeImage(_))
+    ));
+
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image("rect1", image).unwrap();
+
+    let data = inkscape.image_data("rect1").unwrap();
+    assert_eq!(data.mime, "image/png");
+    assert_eq!(
+        data.bytes,
+        std::fs::read("./static/10x10_green.png").unwrap()
+    );
+
+    let pixels = data.to_image().unwrap();
+    assert_eq!((pixels.width(), pixels.height()), (10, 10));
+}
+
+#[test]
+fn resample_image() {
+    let mut inkscape = test_document(
+        A4_ATTRIBUTES,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="30" height="20" />
+  </g>"#,
+    );
+
+    let screenshot = image::DynamicImage::new_rgb8(1000, 500);
+    inkscape
+        .id_to_image_resampled(
+            "rect1",
+            &screenshot,
+            image::ImageOutputFormat::Png,
+            Resample::new(300.0),
+        )
+        .unwrap();
+
+    // 30mm at 300 dpi is 354 pixels
+    let pixels = inkscape.image_data("rect1").unwrap().to_image().unwrap();
+    assert_eq!((pixels.width(), pixels.height()), (354, 177));
+}
+
+#[test]
+fn fit_image() {
+    let mut inkscape = test_document(
+        A4_ATTRIBUTES,
+        r#"<g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="stretch" width="30" height="20" />
+    <rect id="contain" width="30" height="20" />
+    <rect id="cover" width="30" height="20" />
+    <rect id="keep_width" width="30mm" height="20mm" />
+    <rect id="default" width="30" height="20" />
+    <rect id="stretched_cover" width="10" height="10" transform="scale(2,1)" />
+    <rect id="stretched_keep_width" width="10" height="10" transform="scale(2,1)" />
+    <rect id="rotated_cover" width="30" height="10" transform="rotate(90)" />
+  </g>"#,
+    );
+
+    let square = image::DynamicImage::new_rgb8(60, 60);
+    let fits = [
+        ("stretch", Fit::Stretch),
+        ("contain", Fit::Contain(Align::XMinYMid)),
+        ("cover", Fit::Cover(Align::default())),
+        ("keep_width", Fit::KeepWidth),
+        ("stretched_cover", Fit::Cover(Align::default())),
+        ("stretched_keep_width", Fit::KeepWidth),
+        ("rotated_cover", Fit::Cover(Align::default())),
+    ];
+    for (id, fit) in fits {
+        inkscape
+            .id_to_image_fit(id, &square, image::ImageOutputFormat::Png, fit)
+            .unwrap();
+    }
+    let image = EncodedImage::from_dynamic_image(&square, image::ImageOutputFormat::Png).unwrap();
+    inkscape.id_to_image("default", image).unwrap();
+
+    let aspect = |id| {
+        inkscape
+            .object(id)
+            .unwrap()
+            .attribute("preserveAspectRatio")
+            .unwrap()
+    };
+    assert_eq!(aspect("stretch"), "none");
+    assert_eq!(aspect("contain"), "xMinYMid meet");
+    assert_eq!(aspect("cover"), "none");
+    assert_eq!(aspect("default"), "xMidYMid meet");
+
+    // the pixels of the covering image are cropped to the aspect ratio of the placeholder
+    let cover = inkscape.image_data("cover").unwrap().to_image().unwrap();
+    assert_eq!((cover.width(), cover.height()), (60, 40));
+
+    let keep_width = inkscape.object("keep_width").unwrap();
+    assert_eq!(keep_width.attribute("height").unwrap(), "30mm");
+
+    // a square placeholder that is shown twice as wide as it is high
+    let cover = inkscape
+        .image_data("stretched_cover")
+        .unwrap()
+        .to_image()
+        .unwrap();
+    assert_eq!((cover.width(), cover.height()), (60, 30));
+    let keep_width = inkscape.object("stretched_keep_width").unwrap();
+    assert_eq!(keep_width.attribute("height").unwrap(), "20");
+
+    // rotating the placeholder turns the image along with it, so it is cropped to 3:1
+    let cover = inkscape
+        .image_data("rotated_cover")
+        .unwrap()
+        .to_image()
+        .unwrap();
+    assert_eq!((cover.width(), cover.height()), (60, 20));
+}
+
+#[test]
+fn convert_rect_attributes() {
+    let mut inkscape = test_document(
+        XLINK_ATTRIBUTE,
+        r##"<defs id="defs2" />
+  <g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="30" height="20" x="5" y="5" rx="2" inkscape:label="panel"
+       data-panel="a" aria-label="Panel A" role="img" fill="#00ff00" stroke-width="2"
+       transform="rotate(10)" style="fill:#ff0000;stroke:#000000;opacity:0.5"><title id="title1">Panel A</title></rect>
+    <rect id="rect2" width="30" height="20" ry="3" clip-path="url(#clip0)" />
+  </g>"##,
+    );
+
+    let image = || EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image("rect1", image()).unwrap();
+    inkscape
+        .id_to_image_with(
+            "rect2",
+            image(),
+            ImageOptions::default().with_round_corners(),
+        )
+        .unwrap();
+
+    let rect1 = inkscape.object("rect1").unwrap();
+    let names = rect1
+        .attributes()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "id",
+            "width",
+            "height",
+            "x",
+            "y",
+            "inkscape:label",
+            "data-panel",
+            "aria-label",
+            "role",
+            "transform",
+            "style",
+            "xlink:href",
+            "preserveAspectRatio",
+        ]
+    );
+    assert_eq!(rect1.attribute("style").unwrap(), "opacity:0.5");
+
+    // the rounded corners are kept with a clip path, which is itself clipped by the original
+    let rect2 = inkscape.object("rect2").unwrap();
+    assert_eq!(rect2.attribute("clip-path").unwrap(), "url(#clipPath1)");
+    assert!(rect2.attribute("ry").is_none());
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("<title id=\"title1\">Panel A</title></image>"));
+    assert!(output.contains(concat!(
+        r#"<defs id="defs2" ><clipPath id="clipPath1" clipPathUnits="userSpaceOnUse">"#,
+        r#"<rect width="30" height="20" rx="3" ry="3" clip-path="url(#clip0)"/></clipPath></defs>"#
+    )));
+}
+
+#[test]
+fn pattern_fill() {
+    let mut inkscape = test_layer(
+        r##"<rect id="rect1" width="30" height="20" x="5" y="5" rx="2" style="fill:none;stroke:#000000;stroke-width:0.5" />"##,
+    );
+
+    let options = ImageOptions::default().with_pattern_fill();
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image_with("rect1", image, options).unwrap();
+
+    // filling it again reuses the same pattern
+    let image = EncodedImage::from_path("./static/10x10_red.png").unwrap();
+    inkscape.id_to_image_with("rect1", image, options).unwrap();
+
+    let rect = inkscape.object("rect1").unwrap();
+    assert_eq!(rect.kind(), ObjectKind::Rectangle);
+    assert_eq!(
+        rect.attribute("style").unwrap(),
+        "fill:url(#rect1-fill1);stroke:#000000;stroke-width:0.5"
+    );
+    assert_eq!(rect.attribute("rx").unwrap(), "2");
+
+    let red = EncodedImage::from_path("./static/10x10_red.png").unwrap();
+    let red = std::str::from_utf8(red.as_slice()).unwrap();
+
+    // the image of the pattern is found through the fill of the rectangle
+    let data = inkscape.image_data("rect1").unwrap();
+    assert_eq!(data.bytes, std::fs::read("./static/10x10_red.png").unwrap());
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("<pattern").count(), 1);
+    assert!(output.contains(&format!(
+        concat!(
+            r#"<defs id="defs1"><pattern id="rect1-fill1" patternUnits="userSpaceOnUse" x="5" y="5" width="30" height="20">"#,
+            r#"<image x="0" y="0" width="30" height="20" preserveAspectRatio="xMidYMid meet" xlink:href="{}"/>"#,
+            "</pattern></defs>"
+        ),
+        red
+    )));
+}
+
+#[test]
+fn pattern_fill_keeps_shared_patterns() {
+    let mut inkscape = test_document(
+        XLINK_ATTRIBUTE,
+        r##"<defs id="defs1"><pattern id="stripes" width="2" height="2"><image width="2" height="2" xlink:href="stripes.png"/></pattern></defs>
+  <g inkscape:label="Layer 1" inkscape:groupmode="layer" id="layer1">
+    <rect id="rect1" width="30" height="20" style="fill:url(#stripes)" />
+    <rect id="rect2" width="30" height="20" style="fill:url(#stripes)" />
+  </g>"##,
+    );
+
+    let options = ImageOptions::default().with_pattern_fill();
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    inkscape.id_to_image_with("rect1", image, options).unwrap();
+
+    let rect = inkscape.object("rect1").unwrap();
+    assert_eq!(rect.attribute("style").unwrap(), "fill:url(#rect1-fill1)");
+
+    let mut output = Vec::new();
+    inkscape.write_svg(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(r#"xlink:href="stripes.png""#));
+    assert!(
+        output.contains(r#"<rect id="rect2" width="30" height="20" style="fill:url(#stripes)" />"#)
+    );
+}
+
+#[test]
+fn select_by_label() {
+    let mut inkscape = test_layer(
+        r#"<rect id="rect286" inkscape:label="panel_a" width="30" height="20" />
+    <rect id="rect290" inkscape:label="panel_b" width="30" height="20" />
+    <rect id="rect300" inkscape:label="logo" width="10" height="10" />
+    <text id="text1" inkscape:label="panel_title">Title</text>"#,
+    );
+
+    let id = inkscape.select_id(&Selector::label("panel_b")).unwrap();
+    assert_eq!(id, "rect290");
+    assert_eq!(inkscape.dimensions(&id).unwrap(), (30.0, 20.0));
+
+    let panels = Selector::glob("panel_*").unwrap();
+    let matched = inkscape
+        .objects_matching(&panels)
+        .filter_map(|(_, object)| object.id())
+        .collect::<Vec<_>>();
+    assert_eq!(matched, ["rect286", "rect290", "text1"]);
+    assert!(matches!(
+        inkscape.select_id(&panels),
+        Err(SelectError::AmbiguousMatch(_))
+    ));
+    assert!(matches!(
+        inkscape.select_id(&Selector::id("rect1")),
+        Err(SelectError::NoMatch(_))
+    ));
+
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    let report = inkscape.fill_matching([
+        (panels, image.clone()),
+        (Selector::regex("^fig(ure)?_").unwrap(), image),
+    ]);
+
+    // only rectangles and images are filled, so the text is left alone
+    assert_eq!(report.filled, ["rect286", "rect290"]);
+    assert!(report.failed.is_empty());
+    assert_eq!(report.unmatched.len(), 1);
+    assert_eq!(report.unmatched[0].to_string(), "pattern `^fig(ure)?_`");
+    assert_eq!(
+        inkscape.object("rect290").unwrap().kind(),
+        ObjectKind::Image
+    );
+
+    let logo = Selector::glob("logo").unwrap();
+    assert_eq!(inkscape.selector_dimensions(&logo).unwrap(), (10.0, 10.0));
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    assert_eq!(
+        inkscape.selector_to_image(&logo, image.clone()).unwrap(),
+        "rect300"
+    );
+    // a text is not a placeholder, even if its label matches
+    assert!(matches!(
+        inkscape.selector_to_image(&Selector::label("panel_title"), image),
+        Err(SelectImageError::Select(SelectError::NoMatch(_)))
+    ));
+}
+
+#[test]
+fn raw_xml_objects() {
+    let mut inkscape = test_layer(
+        r#"<circle id="circle1" r="5"><title id="title1">Marker</title></circle>
+    <flowRoot id="flowRoot1"><flowPara id="flowPara1">Note</flowPara></flowRoot>
+    <rect id="rect1" width="30" height="20" />
+    <a id="a1"><rect id="rect2" inkscape:label="panel" width="30" height="20" /></a>"#,
+    );
+
+    // the elements inside of unknown elements are not objects of their own, unless they
+    // are placeholders
+    let ids = inkscape
+        .objects()
+        .filter_map(|(_, object)| object.id())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["circle1", "flowRoot1", "rect1", "a1", "rect2"]);
+    let rects = inkscape.select(&"rect".parse().unwrap());
+    assert_eq!(rects.len(), 2);
+    assert_eq!(rects[1].id(), Some("rect2"));
+    assert_eq!(
+        inkscape.select_id(&Selector::label("panel")).unwrap(),
+        "rect2"
+    );
+
+    let image = EncodedImage::from_path("./static/10x10_green.png").unwrap();
+    let report = inkscape.fill_matching([(Selector::label("panel"), image)]);
+    assert_eq!(report.filled, ["rect2"]);
+    assert!(inkscape
+        .select(&"title, flowPara".parse().unwrap())
+        .is_empty());
+
+    // only elements have attributes
+    let whitespace = quick_xml::events::BytesText::new("\n    ");
+    let mut whitespace = Object::raw(Event::Text(whitespace));
+    assert!(whitespace.set_attribute("id", "text1").is_err());
+    assert!(whitespace.remove_attribute("id").is_err());
+}
+
+#[test]
+fn select_query() {
+    let mut inkscape = test_document(
+        "",
+        r#"<g inkscape:label="plots" inkscape:groupmode="layer" id="layer1">
+    <g id="g1">
+      <rect id="rect1" data-role="placeholder" class="panel wide" width="30" height="20" />
+      <circle r="5" data-role="placeholder" />
+    </g>
+    <rect id="rect3" width="30" height="20" />
+    <g inkscape:label="captions" inkscape:groupmode="layer" id="layer2">
+      <text id="text1">A</text>
+    </g>
+  </g>
+  <g inkscape:label="background" inkscape:groupmode="layer" id="layer3">
+    <rect id="rect4" data-role="placeholder" width="30" height="20" />
+  </g>"#,
+    );
+
+    let ids = |query: &str| {
+        inkscape
+            .select(&query.parse().unwrap())
+            .iter()
+            .map(|handle| handle.id().unwrap_or("-").to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids(r#"layer[label="plots"] rect[data-role=placeholder]"#),
+        ["rect1"]
+    );
+    assert_eq!(
+        ids(r#"layer[label="plots"] [data-role=placeholder]"#),
+        ["rect1", "-"]
+    );
+    assert_eq!(ids("layer > rect"), ["rect3", "rect4"]);
+    assert_eq!(ids(".wide, #text1"), ["rect1", "text1"]);
+    assert_eq!(ids("layer"), ["layer1", "layer2", "layer3"]);
+    assert_eq!(ids("layer layer text"), ["text1"]);
+    assert_eq!(ids("g > g"), ["g1", "layer2"]);
+
+    let query = "[data-role^=place]".parse().unwrap();
+    let handles = inkscape.select_mut(&query, |selected| {
+        if let SelectedMut::Object(object) = selected {
+            object.set_attribute("data-role", "filled").unwrap();
+        }
+    });
+    assert_eq!(handles.len(), 3);
+    assert!(!handles[0].is_layer());
 
-    panic!();
+    assert!(inkscape.select(&query).is_empty());
+    assert_eq!(
+        inkscape
+            .object("rect4")
+            .unwrap()
+            .attribute("data-role")
+            .unwrap(),
+        "filled"
+    );
 }
@@ -1,47 +1,903 @@
+use quick_xml::events::BytesEnd;
 use quick_xml::events::BytesStart;
 use quick_xml::events::Event;
 use quick_xml::name::QName;
 use std::io::Read;
+use std::io::Write;
 
 use super::error::*;
+use super::fit::Fit;
+use super::style::Style;
+use super::text::Text;
+use super::transform::Transform;
+use super::units::{Axis, Length, Unit, Viewport};
 
 use std::fmt::Write as _;
 
 use std::path::Path;
+use std::path::PathBuf;
 
-#[derive(Debug)]
-pub(crate) enum Object {
+/// An object inside of a layer, such as a rectangle, image or group
+///
+/// Objects are read from and written to the xml of the document directly, so changing an
+/// attribute is kept exactly as it was set when the document is written
+#[derive(Debug, Clone)]
+pub enum Object {
     Rectangle(Rectangle),
     Image(Image),
+    /// a `<text>` element along with its `<tspan>` lines
+    Text(Text),
+    /// a sublayer (`<g inkscape:groupmode="layer">`) nested inside of another layer
+    Layer(super::Layer),
+    /// a plain `<g>` group of objects, or other content that replaced a placeholder
+    Group(Group),
     /// other does not necessarily have to be a image or geometrical event,
     /// it could also be spacing events
-    Other(Event<'static>),
+    Other(RawXml),
 }
 
+/// xml that is kept exactly as it was read, such as whitespace, comments or an element this
+/// crate does not know about. Such elements can be read and changed through [`Object`]
+#[derive(Debug, Clone)]
+pub struct RawXml(pub(crate) Event<'static>);
+
 impl Object {
-    pub(crate) fn into_event(self) -> Event<'static> {
+    /// an event that is kept as it is
+    pub(crate) fn raw(event: Event<'static>) -> Self {
+        Self::Other(RawXml(event))
+    }
+
+    /// how the object changes the nesting of the raw xml around it: `1` for the start of an
+    /// element that was kept as raw xml, `-1` for its end and `0` otherwise. Raw xml inside
+    /// of such elements belongs to them, but rectangles, images, texts and groups inside of
+    /// them (such as a `<rect>` in a link) are objects of their own
+    pub(crate) fn raw_nesting(&self) -> isize {
+        match self {
+            Self::Other(RawXml(Event::Start(_))) => 1,
+            Self::Other(RawXml(Event::End(_))) => -1,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn write<W: Write>(
+        self,
+        writer: &mut quick_xml::Writer<W>,
+    ) -> Result<(), LayerError> {
+        let event = match self {
+            Self::Rectangle(rect) => return write_element(writer, rect.element, rect.content),
+            Self::Image(image) => return write_element(writer, image.element, image.content),
+            Self::Text(text) => return write_element(writer, text.element, text.content),
+            Self::Layer(layer) => return layer.write(writer),
+            Self::Group(group) => return group.write(writer),
+            Self::Other(RawXml(object)) => object,
+        };
+
+        writer
+            .write_event(&event)
+            .map_err(|err| LayerBody { err, object: event })?;
+
+        Ok(())
+    }
+
+    /// the identifiers of the object, if it is a kind of object we track identifiers for
+    pub(crate) fn ident(&self) -> Option<&Identifiers> {
         match self {
-            Self::Rectangle(rect) => Event::Empty(rect.element),
-            Self::Image(image) => Event::Empty(image.element),
-            Self::Other(object) => object,
+            Self::Rectangle(rect) => Some(&rect.ident),
+            Self::Image(image) => Some(&image.ident),
+            Self::Text(_) | Self::Layer(_) | Self::Group(_) | Self::Other(_) => None,
+        }
+    }
+
+    /// the xml element of the object, if it is a rectangle or image
+    pub(crate) fn element_mut(&mut self) -> Option<&mut BytesStart<'static>> {
+        match self {
+            Self::Rectangle(rect) => Some(&mut rect.element),
+            Self::Image(image) => Some(&mut image.element),
+            Self::Text(_) | Self::Layer(_) | Self::Group(_) | Self::Other(_) => None,
+        }
+    }
+
+    /// the xml element of the object, if it is a rectangle or image
+    pub(crate) fn element(&self) -> Option<&BytesStart<'static>> {
+        match self {
+            Self::Rectangle(rect) => Some(&rect.element),
+            Self::Image(image) => Some(&image.element),
+            Self::Text(_) | Self::Layer(_) | Self::Group(_) | Self::Other(_) => None,
+        }
+    }
+
+    /// the objects nested inside of this object, if it is a group or sublayer
+    pub fn children(&self) -> Option<&[Object]> {
+        match self {
+            Self::Layer(layer) => Some(&layer.content),
+            Self::Group(group) => Some(&group.content),
+            Self::Rectangle(_) | Self::Image(_) | Self::Text(_) | Self::Other(_) => None,
+        }
+    }
+
+    /// the objects nested inside of this object, if it is a group or sublayer
+    pub(crate) fn children_mut(&mut self) -> Option<&mut Vec<Object>> {
+        match self {
+            Self::Layer(layer) => Some(&mut layer.content),
+            Self::Group(group) => Some(&mut group.content),
+            Self::Rectangle(_) | Self::Image(_) | Self::Text(_) | Self::Other(_) => None,
+        }
+    }
+}
+
+/// Public access to the contents of objects
+impl Object {
+    /// what kind of element the object is
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Self::Layer(_) => ObjectKind::Layer,
+            Self::Group(_) => ObjectKind::Group,
+            _ => match self.start_element().map(BytesStart::name) {
+                Some(QName(b"rect")) => ObjectKind::Rectangle,
+                Some(QName(b"image")) => ObjectKind::Image,
+                Some(QName(b"text")) => ObjectKind::Text,
+                Some(QName(b"path")) => ObjectKind::Path,
+                _ => ObjectKind::Other,
+            },
+        }
+    }
+
+    /// the `id` attribute of the object
+    pub fn id(&self) -> Option<String> {
+        self.attribute("id")
+    }
+
+    /// the name of the object shown in inkscape (its `inkscape:label`)
+    pub fn label(&self) -> Option<String> {
+        self.attribute("inkscape:label")
+    }
+
+    /// the (unescaped) value of an attribute of the object
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        self.start_element()
+            .and_then(|element| attribute_value(element, name.as_bytes()))
+    }
+
+    /// every attribute of the object as `(name, value)`, in the order they are written
+    pub fn attributes(&self) -> Vec<(String, String)> {
+        let element = match self.start_element() {
+            Some(element) => element,
+            None => return Vec::new(),
+        };
+
+        element
+            .attributes()
+            .filter_map(Result::ok)
+            .filter_map(|att| {
+                let name = String::from_utf8_lossy(att.key.as_ref()).into_owned();
+                let value = att.unescape_value().ok()?.into_owned();
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    /// set an attribute of the object, keeping its position if it already exists.
+    ///
+    /// Fails without changing anything if the object is a rectangle or image and the new
+    /// value leaves it without a valid `id`, `width` or `height`, or if it is a layer and
+    /// the new value leaves it without an `id` or `inkscape:label`
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Result<(), AttributeError> {
+        self.update_attribute(name, Some(value))
+    }
+
+    /// remove an attribute of the object, see [`Object::set_attribute`]
+    pub fn remove_attribute(&mut self, name: &str) -> Result<(), AttributeError> {
+        self.update_attribute(name, None)
+    }
+
+    /// the position, size and transform of a rectangle or image
+    pub fn geometry(&self) -> Option<Geometry> {
+        self.ident().map(|ident| Geometry {
+            x: ident.x,
+            y: ident.y,
+            width: ident.width,
+            height: ident.height,
+            transform: ident.transform,
+        })
+    }
+
+    /// where the pixels of an `<image>` come from
+    pub fn image_href(&self) -> Option<ImageHref> {
+        if self.kind() != ObjectKind::Image {
+            return None;
+        }
+
+        element_href(self.start_element()?)
+    }
+
+    /// the xml element that starts the object, which is `None` for text, comments
+    /// and closing tags
+    pub(crate) fn start_element(&self) -> Option<&BytesStart<'static>> {
+        match self {
+            Self::Rectangle(Rectangle { element, .. })
+            | Self::Image(Image { element, .. })
+            | Self::Text(Text { element, .. }) => Some(element),
+            Self::Layer(layer) => Some(&layer.header),
+            Self::Group(group) => Some(&group.header),
+            Self::Other(RawXml(event)) => match event {
+                Event::Start(element) | Event::Empty(element) => Some(element),
+                _ => None,
+            },
+        }
+    }
+
+    fn update_attribute(&mut self, name: &str, value: Option<&str>) -> Result<(), AttributeError> {
+        match self {
+            Self::Rectangle(Rectangle { ident, element, .. })
+            | Self::Image(Image { ident, element, .. }) => {
+                let mut new_element = element.clone();
+                set_attribute(&mut new_element, name, value);
+
+                // a position or transform that can not be parsed falls back to its default,
+                // the same as when the document was parsed
+                let (new_ident, _) = Identifiers::parse(&new_element)?;
+                *ident = new_ident;
+                *element = new_element;
+            }
+            Self::Layer(layer) => {
+                let mut header = layer.header.clone();
+                set_attribute(&mut header, name, value);
+
+                // layers are looked up by their id and name, so neither can go missing
+                let id = super::parse::layer_id(&header)?;
+                if id.is_empty() {
+                    return Err(MissingLayerId::new(header).into());
+                }
+                let layer_name = super::parse::layer_name(&header)?;
+                if layer_name.is_empty() {
+                    return Err(MissingLayerName::new(header).into());
+                }
+
+                layer.id = id;
+                layer.name = layer_name;
+                layer.header = header;
+            }
+            Self::Group(group) => set_attribute(&mut group.header, name, value),
+            Self::Text(text) => set_attribute(&mut text.element, name, value),
+            Self::Other(RawXml(Event::Start(element) | Event::Empty(element))) => {
+                set_attribute(element, name, value)
+            }
+            // text, comments and closing tags do not have attributes
+            Self::Other(_) => return Err(NoAttributes.into()),
+        }
+
+        Ok(())
+    }
+}
+
+/// The kind of element an [`Object`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Rectangle,
+    Image,
+    Text,
+    Path,
+    /// a plain `<g>` group
+    Group,
+    /// a sublayer nested inside of another layer
+    Layer,
+    /// any other element, such as `<circle>` or `<use>`
+    Other,
+}
+
+/// The position, size and transform of a rectangle or image, as written in the document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub x: Length,
+    pub y: Length,
+    pub width: Length,
+    pub height: Length,
+    /// the `transform` attribute of the object itself, not including any groups it is in
+    pub transform: Transform,
+}
+
+/// Where the pixels of an `<image>` come from, from its `xlink:href` (or `href`) attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageHref {
+    /// the image is embedded in the document as a `data:{mime};base64,{data}` URI
+    Embedded { mime: String, data: String },
+    /// the image is a separate file (or any other URI) that the document links to
+    Linked(String),
+}
+
+/// The contents of an image embedded in the document, decoded from its data URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    /// the MIME type from the data URI, such as `image/png`
+    pub mime: String,
+    /// the encoded image file, such as the bytes of a PNG
+    pub bytes: Vec<u8>,
+}
+
+impl ImageData {
+    /// decode a `data:{mime};base64,{data}` URI of the image `id`
+    pub(crate) fn from_data_uri(id: &str, href: &str) -> Result<Self, ImageDataError> {
+        let (mime, data) = match parse_data_uri(href) {
+            Some(DataUri::Base64 { mime, data }) => (mime, data),
+            Some(DataUri::Other { header }) => {
+                return Err(UnsupportedEncoding::new(id.into(), header.into()).into())
+            }
+            Some(DataUri::Malformed) => return Err(MalformedDataUri::new(id.into()).into()),
+            None => return Err(NotEmbedded::new(id.into()).into()),
+        };
+
+        let bytes = base64::decode(data).map_err(|error| DecodeImage::new(id.into(), error))?;
+
+        Ok(Self {
+            mime: mime.to_string(),
+            bytes,
+        })
+    }
+
+    /// decode the pixels of the image, in any format the `image` crate supports
+    pub fn to_image(&self) -> Result<image::DynamicImage, DecodePixels> {
+        image::load_from_memory(&self.bytes)
+            .map_err(|error| DecodePixels::new(self.mime.clone(), error))
+    }
+}
+
+/// the namespace `xlink:href` belongs to
+pub(crate) const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// the start of the ids of the `<pattern>`s made to fill the rectangle `placeholder`, such
+/// as `rect1-fill1`
+pub(crate) fn pattern_base_id(placeholder: &str) -> String {
+    format!("{placeholder}-fill")
+}
+
+/// whether the `<pattern>` with id `id` was made to fill the rectangle `placeholder`. Any
+/// other pattern belongs to the designer and is never changed, since it may be shared
+pub(crate) fn is_placeholder_pattern(id: &str, placeholder: &str) -> bool {
+    id.strip_prefix(&pattern_base_id(placeholder))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()))
+}
+
+/// Which attribute an `<image>` keeps its data in
+///
+/// SVG 1.1 (and inkscape) use `xlink:href`, which needs the `xmlns:xlink` namespace to be
+/// declared on the root element. SVG 2 deprecates it in favour of a plain `href`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HrefAttribute {
+    #[default]
+    XlinkHref,
+    Href,
+}
+
+impl HrefAttribute {
+    /// the name of the attribute
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::XlinkHref => "xlink:href",
+            Self::Href => "href",
+        }
+    }
+
+    /// the form of href `element` uses, preferring `xlink:href` if it has both
+    pub(crate) fn of_element(element: &BytesStart<'_>) -> Option<Self> {
+        [Self::XlinkHref, Self::Href]
+            .into_iter()
+            .find(|href| attribute_value(element, href.name().as_bytes()).is_some())
+    }
+}
+
+/// How [`Inkscape::id_to_image_with`](crate::Inkscape::id_to_image_with) puts an image
+/// into a placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImageOptions {
+    /// the attribute a converted `<rect>` stores the image in, or the form the document
+    /// already uses if `None` (see [`Inkscape::href_attribute`](crate::Inkscape::href_attribute))
+    pub href: Option<HrefAttribute>,
+    /// clip the image to the rounded corners (`rx` and `ry`) of a placeholder `<rect>`
+    /// with a `<clipPath>` in the `<defs>` of the document
+    pub round_corners: bool,
+    /// keep a placeholder `<rect>` and fill it with a `<pattern>` of the image in the `<defs>`
+    /// of the document instead of replacing it, so that its stroke, rounded corners and
+    /// filters are kept. The pattern is named after the placeholder (`rect1-fill1`), which
+    /// is how filling the placeholder again finds it
+    pub pattern_fill: bool,
+}
+
+impl ImageOptions {
+    /// store the image of a converted `<rect>` in `href`
+    pub fn with_href(self, href: HrefAttribute) -> Self {
+        Self {
+            href: Some(href),
+            ..self
+        }
+    }
+
+    /// clip the image to the rounded corners of the placeholder
+    pub fn with_round_corners(self) -> Self {
+        Self {
+            round_corners: true,
+            ..self
+        }
+    }
+
+    /// keep the placeholder `<rect>` and fill it with a pattern of the image
+    pub fn with_pattern_fill(self) -> Self {
+        Self {
+            pattern_fill: true,
+            ..self
+        }
+    }
+}
+
+/// properties of a `<rect>` that only mean something for a shape, which are dropped from
+/// the `<image>` replacing it both as attributes and from its `style`, along with every
+/// `stroke-*` property. Everything else (`data-*`, `aria-*`, inkscape metadata, ...) is kept
+const RECT_PROPERTIES: [&str; 15] = [
+    "rx",
+    "ry",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "stroke",
+    "paint-order",
+    "marker",
+    "marker-start",
+    "marker-mid",
+    "marker-end",
+    "vector-effect",
+    "shape-rendering",
+    "inkscape:path-effect",
+    "inkscape:original-d",
+];
+
+/// whether a property of a `<rect>` is dropped from the `<image>` replacing it
+fn is_rect_property(name: &str) -> bool {
+    RECT_PROPERTIES.contains(&name) || name.starts_with("stroke-")
+}
+
+/// whether an attribute of a `<rect>` is kept on the `<image>` replacing it
+fn is_image_attribute(key: QName<'_>) -> bool {
+    !is_href(key) && !is_rect_property(&String::from_utf8_lossy(key.as_ref()))
+}
+
+/// whether an attribute is `xlink:href` or `href`, or the absolute path inkscape keeps
+/// next to a linked image, all of which are stale once the image changes
+fn is_href(key: QName<'_>) -> bool {
+    key == QName(b"xlink:href") || key == QName(b"href") || key == QName(b"sodipodi:absref")
+}
+
+/// the `xlink:href` (or `href`) of an element
+fn element_href(element: &BytesStart<'_>) -> Option<ImageHref> {
+    let href =
+        attribute_value(element, b"xlink:href").or_else(|| attribute_value(element, b"href"))?;
+
+    Some(ImageHref::parse(href))
+}
+
+impl ImageHref {
+    fn parse(href: String) -> Self {
+        match parse_data_uri(&href) {
+            Some(DataUri::Base64 { mime, data }) => Self::Embedded {
+                mime: mime.to_string(),
+                data,
+            },
+            _ => Self::Linked(href),
+        }
+    }
+}
+
+/// the parts of a `data:` URI
+enum DataUri<'a> {
+    /// `data:{mime};base64,{data}`, without any parameters of the MIME type
+    Base64 { mime: &'a str, data: String },
+    /// a URI that is not base64 encoded, with everything between `data:` and the `,`
+    Other { header: &'a str },
+    /// a URI without a `,` or a MIME type
+    Malformed,
+}
+
+/// split a `data:` URI into its parts, `None` if `href` is not one
+fn parse_data_uri(href: &str) -> Option<DataUri<'_>> {
+    let uri = href.strip_prefix("data:")?;
+    let (header, data) = match uri.split_once(',') {
+        Some(split) => split,
+        None => return Some(DataUri::Malformed),
+    };
+
+    // `;base64` is always the last parameter, after any such as `;charset=...`
+    let parameters = match header.strip_suffix(";base64") {
+        Some(parameters) => parameters,
+        None => return Some(DataUri::Other { header }),
+    };
+    let mime = parameters.split(';').next().unwrap_or_default().trim();
+    if !mime.contains('/') {
+        return Some(DataUri::Malformed);
+    }
+
+    Some(DataUri::Base64 {
+        mime,
+        // inkscape wraps long base64 data over several lines
+        data: data.split_whitespace().collect(),
+    })
+}
+
+/// recursively search a tree of objects for any object with a given id
+pub(crate) fn find_object<'a>(content: &'a [Object], id: &str) -> Option<&'a Object> {
+    for object in content {
+        if object.id().as_deref() == Some(id) {
+            return Some(object);
+        }
+
+        if let Some(found) = object
+            .children()
+            .and_then(|children| find_object(children, id))
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// recursively search a tree of objects for any object with a given id
+pub(crate) fn find_object_mut<'a>(content: &'a mut [Object], id: &str) -> Option<&'a mut Object> {
+    for object in content {
+        if object.id().as_deref() == Some(id) {
+            return Some(object);
+        }
+
+        if let Some(found) = object
+            .children_mut()
+            .and_then(|children| find_object_mut(children, id))
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// write a rectangle or image element, either as a single `<element/>` or as
+/// `<element> ... </element>` if it was parsed with child content
+fn write_element<W: Write>(
+    writer: &mut quick_xml::Writer<W>,
+    element: BytesStart<'static>,
+    content: Option<Vec<Event<'static>>>,
+) -> Result<(), LayerError> {
+    let content = if let Some(content) = content {
+        content
+    } else {
+        let event = Event::Empty(element);
+        writer
+            .write_event(&event)
+            .map_err(|err| LayerBody { err, object: event })?;
+
+        return Ok(());
+    };
+
+    // the closing tag is generated from the start tag so that it stays correct if the
+    // element was renamed (from `<rect>` to `<image>`)
+    let end = Event::End(BytesEnd::new(
+        String::from_utf8_lossy(element.name().as_ref()).into_owned(),
+    ));
+
+    let start = Event::Start(element);
+
+    for event in std::iter::once(start)
+        .chain(content)
+        .chain(std::iter::once(end))
+    {
+        writer
+            .write_event(&event)
+            .map_err(|err| LayerBody { err, object: event })?;
+    }
+
+    Ok(())
+}
+
+/// the (unescaped) value of the attribute `key` of an element, if it is present and valid UTF8
+pub(crate) fn attribute_value(element: &BytesStart<'_>, key: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .filter_map(Result::ok)
+        .find(|att| att.key == QName(key))
+        .and_then(|att| att.unescape_value().ok().map(|value| value.into_owned()))
+}
+
+/// set the attribute `key` of an element to `value`, or remove it if `value` is `None`.
+///
+/// An existing attribute keeps its position, a new attribute is added at the end
+pub(crate) fn set_attribute(element: &mut BytesStart<'static>, key: &str, value: Option<&str>) {
+    let mut new_element = element.to_owned();
+    new_element.clear_attributes();
+
+    let mut found = false;
+
+    for att in element.attributes().filter_map(Result::ok) {
+        if att.key != QName(key.as_bytes()) {
+            new_element.push_attribute(att);
+            continue;
+        }
+
+        if let (false, Some(value)) = (found, value) {
+            new_element.push_attribute((key, value));
+        }
+
+        found = true;
+    }
+
+    if let (false, Some(value)) = (found, value) {
+        new_element.push_attribute((key, value));
+    }
+
+    *element = new_element;
+}
+
+/// the parsed `style` attribute of an element, which is empty if it is missing
+pub(crate) fn element_style(element: &BytesStart<'_>) -> Style {
+    attribute_value(element, b"style")
+        .map(|style| style.parse().unwrap_or_default())
+        .unwrap_or_default()
+}
+
+/// replace the `style` attribute of an element, removing it entirely if `style` is empty
+pub(crate) fn set_element_style(element: &mut BytesStart<'static>, style: &Style) {
+    if style.is_empty() {
+        set_attribute(element, "style", None);
+    } else {
+        set_attribute(element, "style", Some(&style.to_string()));
+    }
+}
+
+/// recursively search a tree of objects for a rectangle or image with a given id
+pub(crate) fn find_by_id<'a>(content: &'a [Object], id: &str) -> Option<&'a Object> {
+    for object in content {
+        if object.ident().map(|ident| ident.id == id).unwrap_or(false) {
+            return Some(object);
+        }
+
+        if let Some(found) = object
+            .children()
+            .and_then(|children| find_by_id(children, id))
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// recursively search a tree of objects for a rectangle or image with a given id, along with
+/// the combined transform of every layer and group it is nested in (starting from `parent`)
+pub(crate) fn find_by_id_with_transform<'a>(
+    content: &'a [Object],
+    id: &str,
+    parent: Transform,
+) -> Result<Option<(&'a Object, Transform)>, ParseTransformError> {
+    for object in content {
+        if object.ident().map(|ident| ident.id == id).unwrap_or(false) {
+            return Ok(Some((object, parent)));
+        }
+
+        let (transform, children) = match object {
+            Object::Layer(layer) => (layer.transform()?, &layer.content),
+            Object::Group(group) => (element_transform(&group.header)?, &group.content),
+            Object::Rectangle(_) | Object::Image(_) | Object::Text(_) | Object::Other(_) => {
+                continue
+            }
+        };
+
+        if let Some(found) = find_by_id_with_transform(children, id, parent.then(&transform))? {
+            return Ok(Some(found));
+        }
+    }
+
+    Ok(None)
+}
+
+/// recursively search a tree of objects for a rectangle or image with a given id
+pub(crate) fn find_by_id_mut<'a>(content: &'a mut [Object], id: &str) -> Option<&'a mut Object> {
+    for object in content {
+        if object.ident().map(|ident| ident.id == id).unwrap_or(false) {
+            return Some(object);
+        }
+
+        if let Some(found) = object
+            .children_mut()
+            .and_then(|children| find_by_id_mut(children, id))
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// call `f` on every `<image>` in a tree of objects, stopping at the first error
+pub(crate) fn try_for_each_image_mut<F, E>(content: &mut [Object], f: &mut F) -> Result<(), E>
+where
+    F: FnMut(&mut Image) -> Result<(), E>,
+{
+    for object in content {
+        match object {
+            Object::Image(image) => f(image)?,
+            object => {
+                if let Some(children) = object.children_mut() {
+                    try_for_each_image_mut(children, f)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// call `f` on every xml element in a tree of objects: the headers of layers and groups,
+/// rectangles, images and texts along with any elements inside of them, and all other elements
+pub(crate) fn for_each_element<'a, F>(content: &'a [Object], f: &mut F)
+where
+    F: FnMut(&'a BytesStart<'static>),
+{
+    for object in content {
+        match object {
+            Object::Rectangle(Rectangle {
+                element, content, ..
+            })
+            | Object::Image(Image {
+                element, content, ..
+            })
+            | Object::Text(Text { element, content }) => {
+                f(element);
+                content
+                    .iter()
+                    .flatten()
+                    .for_each(|event| event_element(event, f));
+            }
+            Object::Layer(layer) => layer.for_each_element(f),
+            Object::Group(group) => {
+                f(&group.header);
+                for_each_element(&group.content, f);
+            }
+            Object::Other(RawXml(event)) => event_element(event, f),
         }
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct Rectangle {
-    pub ident: Identifiers,
+/// call `f` on every xml element in a tree of objects, see [`for_each_element`].
+///
+/// The ids of rectangles, images and sublayers are read again afterwards, in case
+/// `f` changed them
+pub(crate) fn for_each_element_mut<F>(content: &mut [Object], f: &mut F)
+where
+    F: FnMut(&mut BytesStart<'static>),
+{
+    for object in content {
+        match object {
+            Object::Rectangle(Rectangle {
+                ident,
+                element,
+                content,
+            })
+            | Object::Image(Image {
+                ident,
+                element,
+                content,
+            }) => {
+                f(element);
+                content
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|event| event_element_mut(event, f));
+
+                if let Some(id) = attribute_value(element, b"id") {
+                    ident.id = id;
+                }
+            }
+            Object::Text(Text { element, content }) => {
+                f(element);
+                content
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|event| event_element_mut(event, f));
+            }
+            Object::Layer(layer) => layer.for_each_element_mut(f),
+            Object::Group(group) => {
+                f(&mut group.header);
+                for_each_element_mut(&mut group.content, f);
+            }
+            Object::Other(RawXml(event)) => event_element_mut(event, f),
+        }
+    }
+}
+
+/// call `f` on every event in a tree of objects that is not the start of a layer, group or
+/// object: the content of rectangles, images and texts, and all other events
+pub(crate) fn for_each_event_mut<F>(content: &mut [Object], f: &mut F)
+where
+    F: FnMut(&mut Event<'static>),
+{
+    for object in content {
+        match object {
+            Object::Rectangle(Rectangle { content, .. })
+            | Object::Image(Image { content, .. })
+            | Object::Text(Text { content, .. }) => content.iter_mut().flatten().for_each(&mut *f),
+            Object::Layer(layer) => for_each_event_mut(&mut layer.content, f),
+            Object::Group(group) => for_each_event_mut(&mut group.content, f),
+            Object::Other(RawXml(event)) => f(event),
+        }
+    }
+}
+
+/// call `f` on the element of a start or empty event
+pub(crate) fn event_element<'a, F>(event: &'a Event<'static>, f: &mut F)
+where
+    F: FnMut(&'a BytesStart<'static>),
+{
+    if let Event::Start(element) | Event::Empty(element) = event {
+        f(element);
+    }
+}
+
+/// call `f` on the element of a start or empty event
+pub(crate) fn event_element_mut<F>(event: &mut Event<'static>, f: &mut F)
+where
+    F: FnMut(&mut BytesStart<'static>),
+{
+    if let Event::Start(element) | Event::Empty(element) = event {
+        f(element);
+    }
+}
+
+#[derive(Debug, Clone)]
+/// a plain `<g> ... </g>` group inside of a layer
+///
+/// unlike layers, groups are not required to have an id or a label
+pub struct Group {
+    pub(crate) header: BytesStart<'static>,
+    pub(crate) content: Vec<Object>,
+    pub(crate) footer: Event<'static>,
+}
+
+impl Group {
+    fn write<W: Write>(self, writer: &mut quick_xml::Writer<W>) -> Result<(), LayerError> {
+        let header = Event::Start(self.header);
+        writer.write_event(&header).map_err(|err| LayerBody {
+            err,
+            object: header,
+        })?;
+
+        for object in self.content {
+            object.write(writer)?;
+        }
+
+        writer.write_event(&self.footer).map_err(|err| LayerBody {
+            err,
+            object: self.footer,
+        })?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// a `<rect>` with an id and a size, which may be replaced by an image
+pub struct Rectangle {
+    pub(crate) ident: Identifiers,
     pub(crate) element: BytesStart<'static>,
+    /// child events (`<title>`, `<desc>`, whitespace) if the element was written as
+    /// `<rect> ... </rect>` instead of `<rect/>`
+    pub(crate) content: Option<Vec<Event<'static>>>,
 }
 
 impl Rectangle {
-    pub(crate) fn set_image(&mut self, base64_encoded: EncodedImage) -> Image {
+    /// an `<image>` with the same identifiers as the rectangle that links to `value`, which
+    /// must already be escaped
+    pub(crate) fn set_href(&mut self, value: &[u8], href: HrefAttribute) -> Image {
         let mut new_element = self.element.to_owned();
         new_element.set_name(b"image").clear_attributes();
 
         let img_data = quick_xml::events::attributes::Attribute {
-            key: QName(b"xlink:href"),
-            value: base64_encoded.as_slice().into(),
+            key: QName(href.name().as_bytes()),
+            value: value.into(),
         };
 
         let new_atts = self
@@ -49,19 +905,124 @@ impl Rectangle {
             .attributes()
             .filter_map(Result::ok)
             // remove attributes from the iterator that are used for rectangular elements
-            .filter(|rect_attribute| rect_attribute.key != QName(b"style"))
+            .filter(|rect_attribute| is_image_attribute(rect_attribute.key))
             // add on the image data
             .chain(std::iter::once(img_data));
 
         // update the element, store it in the current element
         // TODO: this updates the underlying element away from `Rectangle`, which may be confusing
         // in the future
-        let new_element = new_element.with_attributes(new_atts);
+        let mut new_element = new_element.with_attributes(new_atts);
+
+        // the fill and stroke of the placeholder would not do anything on an image
+        let mut style = Style::default();
+        for (property, value) in element_style(&self.element).iter() {
+            if !is_rect_property(property) {
+                style.set(property, value);
+            }
+        }
+        set_element_style(&mut new_element, &style);
+
+        // renderers disagree on how to fit an image without one, so the SVG default is
+        // written out
+        if attribute_value(&new_element, b"preserveAspectRatio").is_none() {
+            let aspect = Fit::default().preserve_aspect_ratio();
+            new_element.push_attribute(("preserveAspectRatio", aspect.as_str()));
+        }
 
         Image {
             ident: self.ident.clone(),
             element: new_element,
+            // keep any <title> or <desc> that was set on the rectangle
+            content: self.content.clone(),
+        }
+    }
+
+    /// the id the `fill` of the rectangle refers to with `url(#id)`, if any
+    pub(crate) fn fill_reference(&self) -> Option<String> {
+        let fill = element_style(&self.element)
+            .get("fill")
+            .map(str::to_string)
+            .or_else(|| attribute_value(&self.element, b"fill"))?;
+
+        let id = fill.trim().strip_prefix("url(")?.strip_suffix(')')?;
+        let id = id.trim().trim_matches(|c| c == '"' || c == '\'');
+
+        id.strip_prefix('#').map(str::to_string)
+    }
+
+    /// fill the rectangle with the paint server (such as a `<pattern>`) with id `id`
+    pub(crate) fn set_fill_reference(&mut self, id: &str) {
+        let mut style = element_style(&self.element);
+        style.set("fill", format!("url(#{id})"));
+        set_element_style(&mut self.element, &style);
+    }
+
+    /// the start of a `<pattern>` with a single tile covering the rectangle
+    pub(crate) fn pattern(&self, id: &str) -> BytesStart<'static> {
+        let mut pattern = BytesStart::new("pattern");
+        pattern.push_attribute(("id", id));
+        // the user space of the pattern is that of the rectangle, including its transform
+        pattern.push_attribute(("patternUnits", "userSpaceOnUse"));
+        for key in ["x", "y", "width", "height"] {
+            if let Some(value) = attribute_value(&self.element, key.as_bytes()) {
+                pattern.push_attribute((key, value.as_str()));
+            }
         }
+
+        pattern
+    }
+
+    /// the `<image>` inside of a pattern from [`Rectangle::pattern`], linking to `value`
+    /// which must already be escaped
+    pub(crate) fn pattern_image(&self, value: &[u8], href: HrefAttribute) -> BytesStart<'static> {
+        let mut image = BytesStart::new("image");
+        // the content of the pattern is relative to the corner of the tile
+        image.push_attribute(("x", "0"));
+        image.push_attribute(("y", "0"));
+        for key in ["width", "height"] {
+            if let Some(value) = attribute_value(&self.element, key.as_bytes()) {
+                image.push_attribute((key, value.as_str()));
+            }
+        }
+        let aspect = Fit::default().preserve_aspect_ratio();
+        image.push_attribute(("preserveAspectRatio", aspect.as_str()));
+        image.push_attribute(quick_xml::events::attributes::Attribute {
+            key: QName(href.name().as_bytes()),
+            value: value.to_vec().into(),
+        });
+
+        image
+    }
+
+    /// a `<rect>` with the position, size and rounded corners of this one, for a
+    /// `<clipPath>` that gives an image the same shape. `None` if the corners are not rounded
+    pub(crate) fn corner_clip(&self) -> Option<BytesStart<'static>> {
+        let rx = attribute_value(&self.element, b"rx");
+        let ry = attribute_value(&self.element, b"ry");
+
+        // a missing radius is the same as the other one
+        let (rx, ry) = match (rx, ry) {
+            (Some(rx), Some(ry)) => (rx, ry),
+            (Some(r), None) | (None, Some(r)) => (r.clone(), r),
+            (None, None) => return None,
+        };
+
+        let is_zero = |r: &str| r.parse::<Length>().map_or(true, |r| r.value <= 0.0);
+        if is_zero(&rx) && is_zero(&ry) {
+            return None;
+        }
+
+        let mut clip = BytesStart::new("rect");
+        for key in ["x", "y", "width", "height"] {
+            if let Some(value) = attribute_value(&self.element, key.as_bytes()) {
+                clip.push_attribute((key, value.as_str()));
+            }
+        }
+        clip.push_attribute(("rx", rx.as_str()));
+        clip.push_attribute(("ry", ry.as_str()));
+
+        Some(clip)
     }
 
     #[cfg(test)]
@@ -69,29 +1030,40 @@ impl Rectangle {
         Self {
             ident,
             element: BytesStart::new("rect"),
+            content: None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// an image with base64 encoding in inkscape
 ///
-/// actual content of the image is stored in the xlink:href attribute
+/// actual content of the image is stored in the xlink:href (or SVG 2 href) attribute
 /// of the element field.
-pub(crate) struct Image {
-    pub ident: Identifiers,
+pub struct Image {
+    pub(crate) ident: Identifiers,
     pub(crate) element: BytesStart<'static>,
+    /// child events (`<title>`, `<desc>`, whitespace) if the element was written as
+    /// `<image> ... </image>` instead of `<image/>`
+    pub(crate) content: Option<Vec<Event<'static>>>,
 }
 
 impl Image {
-    pub(crate) fn update_image(&mut self, base64_encoded: EncodedImage) {
+    /// point the image at `value`, which must already be escaped, in whichever of
+    /// `xlink:href` and `href` the element already uses. `href` is only used if the element
+    /// has neither
+    pub(crate) fn set_href(&mut self, value: &[u8], href: HrefAttribute) {
         //let new_element = quick_xml::events::BytesStart::owned_name(b"image".to_vec());
         let mut new_element = self.element.to_owned();
         new_element.clear_attributes();
 
+        // an element with both forms would keep showing the old data in renderers that
+        // prefer the other one, so only a single attribute is written
+        let href = HrefAttribute::of_element(&self.element).unwrap_or(href);
+
         let img_data = quick_xml::events::attributes::Attribute {
-            key: QName(b"xlink:href"),
-            value: base64_encoded.as_slice().into(),
+            key: QName(href.name().as_bytes()),
+            value: value.into(),
         };
 
         let new_atts = self
@@ -99,7 +1071,7 @@ impl Image {
             .attributes()
             .filter_map(Result::ok)
             // remove attributes from the iterator that are used for image elements
-            .filter(|rect_attribute| rect_attribute.key != QName(b"xlink:href"))
+            .filter(|rect_attribute| !is_href(rect_attribute.key))
             // add on the image data
             .chain(std::iter::once(img_data));
 
@@ -110,11 +1082,22 @@ impl Image {
         self.element = new_element;
     }
 
+    /// where the pixels of the image come from
+    pub(crate) fn href(&self) -> Option<ImageHref> {
+        element_href(&self.element)
+    }
+
+    /// the absolute path inkscape stores next to a link, in case the document is moved
+    pub(crate) fn absref(&self) -> Option<String> {
+        attribute_value(&self.element, b"sodipodi:absref")
+    }
+
     #[cfg(test)]
     pub(crate) fn from_ident(ident: Identifiers) -> Self {
         Self {
             ident,
             element: BytesStart::new("image"),
+            content: None,
         }
     }
 }
@@ -122,8 +1105,12 @@ impl Image {
 #[derive(Debug, Clone)]
 pub(crate) struct Identifiers {
     pub(crate) id: String,
-    pub(crate) width: f64,
-    pub(crate) height: f64,
+    pub(crate) width: Length,
+    pub(crate) height: Length,
+    pub(crate) x: Length,
+    pub(crate) y: Length,
+    /// the `transform` attribute of the element itself, not including any groups it is in
+    pub(crate) transform: Transform,
 }
 
 impl Identifiers {
@@ -131,44 +1118,96 @@ impl Identifiers {
     pub(crate) fn zeros_with_id<T: Into<String>>(id: T) -> Self {
         Self {
             id: id.into(),
-            width: 0.0,
-            height: 0.0,
+            width: Length::user(0.0),
+            height: Length::user(0.0),
+            x: Length::user(0.0),
+            y: Length::user(0.0),
+            transform: Transform::IDENTITY,
         }
     }
 
-    pub(crate) fn from_elem(elem: &BytesStart<'static>) -> Result<Self, IdentifierError> {
+    /// the width and height of the object in user units
+    pub(crate) fn user_dimensions(&self, viewport: &Viewport) -> (f64, f64) {
+        (
+            viewport.to_user_units(self.width, Axis::Horizontal),
+            viewport.to_user_units(self.height, Axis::Vertical),
+        )
+    }
+
+    /// the x and y position of the object in user units, before it is transformed
+    pub(crate) fn user_position(&self, viewport: &Viewport) -> (f64, f64) {
+        (
+            viewport.to_user_units(self.x, Axis::Horizontal),
+            viewport.to_user_units(self.y, Axis::Vertical),
+        )
+    }
+
+    /// whether the position or size are written in units other than user units,
+    /// and therefore need the document's [`Viewport`] to be understood
+    pub(crate) fn has_units(&self) -> bool {
+        [self.width, self.height, self.x, self.y]
+            .iter()
+            .any(|length| length.unit != Unit::User)
+    }
+
+    /// the identifiers of a rectangle or image. Without an `id`, `width` and `height` the
+    /// object can not be filled, but an `x`, `y` or `transform` that can not be parsed
+    /// only falls back to its default, and is returned along with the identifiers
+    pub(crate) fn parse(
+        elem: &BytesStart<'static>,
+    ) -> Result<(Self, Vec<IdentifierError>), IdentifierError> {
         const WIDTH: QName = QName(b"width");
         const HEIGHT: QName = QName(b"height");
         const ID: QName = QName(b"id");
+        const X: QName = QName(b"x");
+        const Y: QName = QName(b"y");
+        const TRANSFORM: QName = QName(b"transform");
 
         let atts = elem
             .attributes()
             .filter_map(Result::ok)
-            .filter(|att| att.key == WIDTH || att.key == HEIGHT || att.key == ID);
+            .filter(|att| [WIDTH, HEIGHT, ID, X, Y, TRANSFORM].contains(&att.key));
 
         let mut width = None;
         let mut height = None;
         let mut id = None;
+        // the position of rectangles and images defaults to zero
+        let mut x = Length::user(0.0);
+        let mut y = Length::user(0.0);
+        let mut transform = Transform::IDENTITY;
+        let mut problems = Vec::new();
+
+        let length = |value: &[u8], dimension: DimensionOrId| -> Result<Length, IdentifierError> {
+            let number = String::from_utf8(value.to_vec())
+                .map_err(|err| DimensionUtf8::new(err, dimension))?;
+
+            let length = number
+                .parse()
+                .map_err(|err| DimensionParse::new(err, dimension))?;
+
+            Ok(length)
+        };
 
         for att in atts {
             if att.key == WIDTH {
-                let number = String::from_utf8(att.value.to_vec())
-                    .map_err(|err| DimensionUtf8::new(err, DimensionOrId::Width))?;
-
-                width = Some(
-                    number
-                        .parse()
-                        .map_err(|err| DimensionParse::new(err, DimensionOrId::Width))?,
-                );
+                width = Some(length(&att.value, DimensionOrId::Width)?);
             } else if att.key == HEIGHT {
-                let number = String::from_utf8(att.value.to_vec())
-                    .map_err(|err| DimensionUtf8::new(err, DimensionOrId::Height))?;
-
-                height = Some(
-                    number
-                        .parse()
-                        .map_err(|err| DimensionParse::new(err, DimensionOrId::Width))?,
-                );
+                height = Some(length(&att.value, DimensionOrId::Height)?);
+            } else if att.key == X {
+                match length(&att.value, DimensionOrId::X) {
+                    Ok(length) => x = length,
+                    Err(problem) => problems.push(problem),
+                }
+            } else if att.key == Y {
+                match length(&att.value, DimensionOrId::Y) {
+                    Ok(length) => y = length,
+                    Err(problem) => problems.push(problem),
+                }
+            } else if att.key == TRANSFORM {
+                match element_transform(elem) {
+                    Ok(parsed) => transform = parsed,
+                    Err(problem) => problems.push(problem.into()),
+                }
             } else if att.key == ID {
                 let id_utf8 = String::from_utf8(att.value.to_vec())
                     .map_err(|err| DimensionUtf8::new(err, DimensionOrId::Id))?;
@@ -177,52 +1216,387 @@ impl Identifiers {
         }
 
         let out = match (width, height, id) {
-            (Some(width), Some(height), Some(id)) => Identifiers { id, width, height },
+            (Some(width), Some(height), Some(id)) => Identifiers {
+                id,
+                width,
+                height,
+                x,
+                y,
+                transform,
+            },
             (w, h, id) => return Err(MissingObjectIdentifier::new(elem.clone(), w, h, id).into()),
         };
 
-        Ok(out)
+        Ok((out, problems))
+    }
+}
+
+/// parse the `transform` attribute of an element, which is the identity transform if missing
+pub(crate) fn element_transform(
+    element: &BytesStart<'_>,
+) -> Result<Transform, ParseTransformError> {
+    match attribute_value(element, b"transform") {
+        Some(transform) => transform.parse(),
+        None => Ok(Transform::IDENTITY),
     }
 }
 
+/// How an image is resampled to the size of its placeholder before it is embedded, see
+/// [`Inkscape::id_to_image_resampled`](crate::Inkscape::id_to_image_resampled)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resample {
+    /// the resolution the document will be printed or exported at
+    pub dpi: f64,
+    /// the filter used to resize the image
+    pub filter: image::imageops::FilterType,
+    /// whether an image with fewer pixels than the placeholder needs is enlarged.
+    /// Otherwise it is embedded as it is
+    pub upsample: bool,
+}
+
+impl Resample {
+    /// downsample to `dpi` with a Lanczos filter, never upsampling
+    pub fn new(dpi: f64) -> Self {
+        Self {
+            dpi,
+            filter: image::imageops::FilterType::Lanczos3,
+            upsample: false,
+        }
+    }
+
+    /// resize with `filter` instead of Lanczos
+    pub fn with_filter(self, filter: image::imageops::FilterType) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// also enlarge images that have fewer pixels than `dpi` needs
+    pub fn with_upsampling(self) -> Self {
+        Self {
+            upsample: true,
+            ..self
+        }
+    }
+
+    /// resize `image` to cover `(width, height)` pixels the way an `<image>` fits its
+    /// content by default (`preserveAspectRatio="xMidYMid meet"`), keeping its aspect ratio.
+    /// `None` if the image does not need to be resized
+    pub(crate) fn resize(
+        &self,
+        image: &image::DynamicImage,
+        (width, height): (f64, f64),
+    ) -> Option<image::DynamicImage> {
+        let (image_width, image_height) = (image.width() as f64, image.height() as f64);
+        let scale = (width / image_width).min(height / image_height);
+
+        if !scale.is_finite() || scale <= 0.0 || scale == 1.0 || (scale > 1.0 && !self.upsample) {
+            return None;
+        }
+
+        let new_width = (image_width * scale).round().max(1.0) as u32;
+        let new_height = (image_height * scale).round().max(1.0) as u32;
+        if (new_width, new_height) == (image.width(), image.height()) {
+            return None;
+        }
+
+        Some(image.resize_exact(new_width, new_height, self.filter))
+    }
+}
+
+/// How images that are not already PNG or JPEG encoded are embedded in the document
+///
+/// PNG and JPEG images are always embedded with their original bytes, since every
+/// renderer supports them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transcode {
+    /// decode the image and re-encode it as a PNG
+    #[default]
+    Png,
+    /// embed the original bytes with their own MIME type (`image/webp`, `image/gif`, ...)
+    Original,
+}
+
+#[derive(Clone)]
 pub struct EncodedImage {
     // base64 encoded bytes with Inkscape mime type prefixed
     base64_bytes: Vec<u8>,
 }
 
 impl EncodedImage {
-    fn as_slice(&self) -> &[u8] {
+    pub(crate) fn as_slice(&self) -> &[u8] {
         self.base64_bytes.as_slice()
     }
 
+    /// encode an image file. Formats other than PNG and JPEG are transcoded to PNG,
+    /// see [`EncodedImage::from_path_with`] to embed them as they are
     pub fn from_path<T: AsRef<Path>>(path: T) -> Result<Self, EncodingError> {
+        Self::from_path_with(path, Transcode::default())
+    }
+
+    /// encode an image file, choosing how formats other than PNG and JPEG are embedded
+    pub fn from_path_with<T: AsRef<Path>>(
+        path: T,
+        transcode: Transcode,
+    ) -> Result<Self, EncodingError> {
         let path = path.as_ref();
 
         let mut file =
-            std::fs::File::open(&path).map_err(|err| OpenFile::new(err, path.to_owned()))?;
+            std::fs::File::open(path).map_err(|err| OpenFile::new(err, path.to_owned()))?;
 
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes)
-            .map_err(|err| ReadBytes::new(err, path.to_owned()))?;
+            .map_err(|err| ReadBytes::new(err, ImageSource::Path(path.to_owned())))?;
 
-        let format = image::guess_format(&bytes).map_err(|_| UnknownMime::new(path.to_owned()))?;
+        Self::encode_bytes(&bytes, transcode, ImageSource::Path(path.to_owned()))
+    }
 
-        if !matches!(format, image::ImageFormat::Png) {
-            return Err(WrongEncoding::new(path.to_owned()).into());
-        }
+    /// encode the bytes of an image file that is already in memory. The format is detected
+    /// from the bytes themselves, and formats other than PNG and JPEG are transcoded to PNG
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EncodingError> {
+        Self::from_bytes_with(bytes, Transcode::default())
+    }
 
+    /// encode the bytes of an image file that is already in memory, choosing how formats other
+    /// than PNG and JPEG are embedded
+    pub fn from_bytes_with(bytes: &[u8], transcode: Transcode) -> Result<Self, EncodingError> {
+        Self::encode_bytes(bytes, transcode, ImageSource::Memory)
+    }
+
+    /// encode an image file read from `reader`. Formats other than PNG and JPEG are
+    /// transcoded to PNG
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, EncodingError> {
+        Self::from_reader_with(reader, Transcode::default())
+    }
+
+    /// encode an image file read from `reader`, choosing how formats other
+    /// than PNG and JPEG are embedded
+    pub fn from_reader_with<R: Read>(
+        mut reader: R,
+        transcode: Transcode,
+    ) -> Result<Self, EncodingError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|err| ReadBytes::new(err, ImageSource::Reader))?;
+
+        Self::encode_bytes(&bytes, transcode, ImageSource::Reader)
+    }
+
+    /// encode a decoded image with the output encoding `format`
+    pub fn from_dynamic_image(
+        image: &image::DynamicImage,
+        format: image::ImageOutputFormat,
+    ) -> Result<Self, EncodingError> {
+        Self::encode_image(format, |cursor, format| image.write_to(cursor, format))
+    }
+
+    /// encode an RGBA image buffer with the output encoding `format`
+    pub fn from_rgba_image(
+        image: &image::RgbaImage,
+        format: image::ImageOutputFormat,
+    ) -> Result<Self, EncodingError> {
+        Self::encode_image(format, |cursor, format| image.write_to(cursor, format))
+    }
+
+    /// sniff the format of an encoded image and embed it according to `transcode`
+    fn encode_bytes(
+        bytes: &[u8],
+        transcode: Transcode,
+        source: ImageSource,
+    ) -> Result<Self, EncodingError> {
+        let format = image::guess_format(bytes).map_err(|_| UnknownMime::new(source.clone()))?;
+
+        let encoded = match (format, transcode) {
+            (image::ImageFormat::Png | image::ImageFormat::Jpeg, _) | (_, Transcode::Original) => {
+                let mime = mime_type(format).ok_or_else(|| WrongEncoding::new(source))?;
+                Self::from_encoded_bytes(mime, bytes)
+            }
+            (_, Transcode::Png) => {
+                let png =
+                    transcode_png(bytes, format).map_err(|err| TranscodeImage::new(err, source))?;
+                Self::from_encoded_bytes("image/png", &png)
+            }
+        };
+
+        Ok(encoded)
+    }
+
+    /// run `write` to encode an image in memory with the output encoding `format`
+    fn encode_image<F>(format: image::ImageOutputFormat, write: F) -> Result<Self, EncodingError>
+    where
+        F: FnOnce(
+            &mut std::io::Cursor<&mut Vec<u8>>,
+            image::ImageOutputFormat,
+        ) -> Result<(), image::ImageError>,
+    {
+        let mime =
+            output_mime_type(&format).ok_or_else(|| WrongEncoding::new(ImageSource::Memory))?;
+
+        let mut bytes = Vec::new();
+        write(&mut std::io::Cursor::new(&mut bytes), format)
+            .map_err(|err| TranscodeImage::new(err, ImageSource::Memory))?;
+
+        Ok(Self::from_encoded_bytes(mime, &bytes))
+    }
+
+    /// base64 encode bytes that are already in the encoding described by `mime`
+    fn from_encoded_bytes(mime: &str, bytes: &[u8]) -> Self {
         let mut base64_buf = String::with_capacity(bytes.len());
 
         // add some inkscape MIME data to the start of the output
-        write!(base64_buf, "data:image/png;base64,").unwrap();
+        write!(base64_buf, "data:{mime};base64,").unwrap();
 
         // encode the bytes as base64
         base64::encode_config_buf(bytes, base64::STANDARD, &mut base64_buf);
 
-        Ok(Self {
+        Self {
             base64_bytes: base64_buf.into_bytes(),
+        }
+    }
+}
+
+/// the MIME type for image formats that may be embedded in a data URI
+fn mime_type(format: image::ImageFormat) -> Option<&'static str> {
+    let mime = match format {
+        image::ImageFormat::Png => "image/png",
+        image::ImageFormat::Jpeg => "image/jpeg",
+        image::ImageFormat::Gif => "image/gif",
+        image::ImageFormat::WebP => "image/webp",
+        image::ImageFormat::Bmp => "image/bmp",
+        image::ImageFormat::Tiff => "image/tiff",
+        _ => return None,
+    };
+
+    Some(mime)
+}
+
+/// the file extension for an image with the MIME type `mime`, ignoring any parameters
+/// such as `;charset=utf-8`
+pub(crate) fn extension(mime: &str) -> String {
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+
+    let extension = match mime {
+        "image/jpeg" => "jpg",
+        "image/tiff" => "tif",
+        "image/svg+xml" => "svg",
+        // `image/png`, `image/gif`, `image/webp`, ...
+        other => other.rsplit('/').next().unwrap_or(other),
+    };
+
+    match file_name(extension).trim_start_matches('_') {
+        "" => "bin".into(),
+        extension => extension.into(),
+    }
+}
+
+/// `name` with everything other than ascii letters, digits, `_`, `-` and `.` replaced by
+/// `_`, and without a leading `.`, so that it always names a file inside of a directory
+pub(crate) fn file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => c,
+            _ => '_',
         })
+        .collect::<String>();
+
+    match name.strip_prefix('.') {
+        Some(rest) => format!("_{rest}"),
+        None if name.is_empty() => "_".into(),
+        None => name,
+    }
+}
+
+/// the href of a link to the file at `path`, which always uses `/` between directories
+pub(crate) fn path_to_link(path: &Path) -> String {
+    let mut link = String::new();
+
+    for component in path.components() {
+        match component {
+            std::path::Component::RootDir => link.push('/'),
+            other => {
+                if !link.is_empty() && !link.ends_with('/') {
+                    link.push('/');
+                }
+                link.push_str(&other.as_os_str().to_string_lossy());
+            }
+        }
+    }
+
+    link
+}
+
+/// the path of a file an image links to, or `None` for data URIs and links to remote
+/// resources. The escapes of `file://` URIs are decoded
+pub(crate) fn link_to_path(link: &str) -> Option<PathBuf> {
+    if let Some(uri) = link.strip_prefix("file://") {
+        // `file://localhost/a.png` is the same file as `file:///a.png`
+        let path = uri.strip_prefix("localhost").unwrap_or(uri);
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+
+    if link.starts_with("data:") || link.contains("://") || link.starts_with('#') {
+        return None;
     }
+
+    Some(PathBuf::from(link))
+}
+
+/// decode the `%XX` escapes of a URI, keeping any `%` that does not start one
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = match bytes.get(index..index + 3) {
+            Some([b'%', high, low]) if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                std::str::from_utf8(&bytes[index + 1..index + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// the MIME type for an encoding that images can be written to in memory
+fn output_mime_type(format: &image::ImageOutputFormat) -> Option<&'static str> {
+    let mime = match format {
+        image::ImageOutputFormat::Png => "image/png",
+        image::ImageOutputFormat::Jpeg(_) => "image/jpeg",
+        image::ImageOutputFormat::Gif => "image/gif",
+        image::ImageOutputFormat::Bmp => "image/bmp",
+        image::ImageOutputFormat::Tiff => "image/tiff",
+        _ => return None,
+    };
+
+    Some(mime)
+}
+
+/// decode an image in `format` and re-encode it as a PNG
+fn transcode_png(bytes: &[u8], format: image::ImageFormat) -> Result<Vec<u8>, image::ImageError> {
+    let image = image::load_from_memory_with_format(bytes, format)?;
+
+    let mut png = Vec::new();
+    image.write_to(
+        &mut std::io::Cursor::new(&mut png),
+        image::ImageOutputFormat::Png,
+    )?;
+
+    Ok(png)
 }
 
 #[test]
@@ -272,7 +1646,7 @@ FUlEQVQY02MMaBRnwA2YGPCCkSoNACS6APwkkpJNAAAAAElFTkSuQmCC
         panic!("did not parse element as image, this should not happen");
     };
 
-    image.update_image(encoded_bytes);
+    image.set_href(encoded_bytes.as_slice(), HrefAttribute::XlinkHref);
 
     // pull out the element from the structure to ensure that we have changed it how we expected to
     let output_image = image
@@ -283,13 +1657,13 @@ FUlEQVQY02MMaBRnwA2YGPCCkSoNACS6APwkkpJNAAAAAElFTkSuQmCC
         .unwrap();
 
     // convert to a string for ease of comparison
-    let output_value = String::from_utf8(output_image.value.to_owned().to_vec()).unwrap();
+    let output_value = String::from_utf8(output_image.value.to_vec()).unwrap();
 
     // ensure that the image has actually changed
     // here QmCC is a string from the end of the above element -
     // if the element was updated correctly then the string should
     // not be present in the new image data
-    assert_eq!(false, output_value.contains("QmCC"));
+    assert!(!output_value.contains("QmCC"));
 }
 
 #[test]
@@ -326,7 +1700,7 @@ fn update_rectangle() {
         panic!("did not parse element as image, this should not happen");
     };
 
-    let image = rect.set_image(encoded_bytes);
+    let image = rect.set_href(encoded_bytes.as_slice(), HrefAttribute::XlinkHref);
 
     // pull out the element from the structure to ensure that we have changed it how we expected to
     let output_image = image
@@ -337,12 +1711,12 @@ fn update_rectangle() {
         .unwrap();
 
     // convert to a string for ease of comparison
-    let output_value = String::from_utf8(output_image.value.to_owned().to_vec()).unwrap();
+    let output_value = String::from_utf8(output_image.value.to_vec()).unwrap();
 
     dbg!(&output_value);
 
     // ensure that there is an image data section on the new element
-    assert_eq!(true, output_value.contains("data:image/png;"));
+    assert!(output_value.contains("data:image/png;"));
     assert_eq!(QName(b"image"), image.element.name());
 }
 
@@ -351,3 +1725,133 @@ fn base64_encode_bytes() {
     let img_path = "./static/10x10_green.png";
     EncodedImage::from_path(img_path).unwrap();
 }
+
+#[test]
+fn encode_jpeg_without_transcoding() {
+    let encoded = EncodedImage::from_path("./static/10x10_green.jpg").unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+
+    assert!(encoded.starts_with("data:image/jpeg;base64,"));
+}
+
+#[test]
+fn encode_other_formats() {
+    for path in ["./static/10x10_green.gif", "./static/10x10_green.bmp"] {
+        let encoded = EncodedImage::from_path(path).unwrap();
+        let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+        assert!(encoded.starts_with("data:image/png;base64,"));
+    }
+
+    let encoded =
+        EncodedImage::from_path_with("./static/10x10_green.gif", Transcode::Original).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/gif;base64,"));
+
+    let encoded =
+        EncodedImage::from_path_with("./static/10x10_green.bmp", Transcode::Original).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/bmp;base64,"));
+}
+
+#[test]
+fn encode_from_memory() {
+    let bytes = std::fs::read("./static/10x10_green.jpg").unwrap();
+    let encoded = EncodedImage::from_bytes(&bytes).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/jpeg;base64,"));
+
+    let file = std::fs::File::open("./static/10x10_green.gif").unwrap();
+    let encoded = EncodedImage::from_reader(file).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/png;base64,"));
+
+    // bytes that are not an image at all
+    let error = EncodedImage::from_bytes(b"not an image");
+    assert!(matches!(error, Err(EncodingError::UnknownMime(_))));
+}
+
+#[test]
+fn encode_decoded_image() {
+    let image = image::open("./static/10x10_green.png").unwrap();
+
+    let encoded =
+        EncodedImage::from_dynamic_image(&image, image::ImageOutputFormat::Jpeg(90)).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/jpeg;base64,"));
+
+    let encoded =
+        EncodedImage::from_rgba_image(&image.to_rgba8(), image::ImageOutputFormat::Png).unwrap();
+    let encoded = std::str::from_utf8(encoded.as_slice()).unwrap();
+    assert!(encoded.starts_with("data:image/png;base64,"));
+
+    // there is no MIME type we can embed a farbfeld image with
+    let error = EncodedImage::from_dynamic_image(&image, image::ImageOutputFormat::Farbfeld);
+    assert!(matches!(error, Err(EncodingError::WrongEncoding(_))));
+}
+
+#[test]
+fn resample_to_placeholder() {
+    let image = image::DynamicImage::new_rgba8(1000, 500);
+
+    // the width limits how large the image is shown
+    let resized = Resample::new(300.0).resize(&image, (354.3, 500.0)).unwrap();
+    assert_eq!((resized.width(), resized.height()), (354, 177));
+
+    let small = image::DynamicImage::new_rgba8(100, 50);
+    assert!(Resample::new(300.0)
+        .resize(&small, (354.3, 500.0))
+        .is_none());
+
+    let resized = Resample::new(300.0)
+        .with_upsampling()
+        .resize(&small, (354.3, 500.0))
+        .unwrap();
+    assert_eq!((resized.width(), resized.height()), (354, 177));
+}
+
+#[test]
+fn file_names() {
+    assert_eq!(file_name("../../x"), "_._.._x");
+    assert_eq!(file_name("..\\x"), "_._x");
+    assert_eq!(file_name(".hidden"), "_hidden");
+    assert_eq!(file_name("rect286"), "rect286");
+
+    assert_eq!(extension("image/png;charset=utf-8"), "png");
+    assert_eq!(extension("image/svg+xml"), "svg");
+    assert_eq!(extension("image/../../x"), "x");
+    assert_eq!(extension(""), "bin");
+}
+
+#[test]
+fn link_paths() {
+    assert_eq!(
+        link_to_path("plots/a.png"),
+        Some(PathBuf::from("plots/a.png"))
+    );
+    assert_eq!(
+        link_to_path("file:///home/me/my%20plots/a%2B.png"),
+        Some(PathBuf::from("/home/me/my plots/a+.png"))
+    );
+    assert_eq!(
+        link_to_path("file://localhost/a%zz.png"),
+        Some(PathBuf::from("/a%zz.png"))
+    );
+    assert_eq!(link_to_path("data:image/svg+xml;utf8,<svg/>"), None);
+    assert_eq!(link_to_path("https://example.com/a.png"), None);
+}
+
+#[test]
+fn data_uri_parameters() {
+    let href = "data:image/png;charset=utf-8;base64,iVBO\n  Rw0K".to_string();
+    assert_eq!(
+        ImageHref::parse(href.clone()),
+        ImageHref::Embedded {
+            mime: "image/png".into(),
+            data: "iVBORw0K".into(),
+        }
+    );
+
+    let data = ImageData::from_data_uri("image1", &href).unwrap();
+    assert_eq!(data.mime, "image/png");
+    assert_eq!(data.bytes, b"\x89PNG\r\n");
+}
@@ -1,52 +1,222 @@
 use super::object;
+use super::text::Text;
 use super::Layer;
 
 use super::error::*;
 
+use quick_xml::events::BytesEnd;
 use quick_xml::events::BytesStart;
 use quick_xml::events::Event;
 use quick_xml::name::QName;
 
 use std::io::BufRead;
+use std::io::Read;
+
+/// the xml reader used for parsing documents, which keeps track of where lines start
+pub(crate) type XmlReader<R> = quick_xml::Reader<LineReader<R>>;
+
+/// A position in the text of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPosition {
+    /// the number of bytes before the position
+    pub offset: usize,
+    /// the line number, starting at 1
+    pub line: usize,
+    /// the number of bytes into the line, starting at 1
+    pub column: usize,
+}
+
+impl std::fmt::Display for TextPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {} (byte {})",
+            self.line, self.column, self.offset
+        )
+    }
+}
+
+/// Wraps the reader of a document to remember where every line starts, so that the byte
+/// offsets quick-xml reports can be turned into lines and columns
+pub(crate) struct LineReader<R> {
+    inner: R,
+    /// the number of bytes consumed so far
+    consumed: usize,
+    /// the offset of the first byte of every line after the first one
+    line_starts: Vec<usize>,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            consumed: 0,
+            line_starts: Vec::new(),
+        }
+    }
+
+    /// the line and column of a byte offset that has already been read
+    pub(crate) fn position(&self, offset: usize) -> TextPosition {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = line
+            .checked_sub(1)
+            .map(|index| self.line_starts[index])
+            .unwrap_or(0);
+
+        TextPosition {
+            offset,
+            line: line + 1,
+            column: offset - line_start + 1,
+        }
+    }
+}
+
+impl<R: BufRead> Read for LineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let amount = available.len().min(buf.len());
+        buf[..amount].copy_from_slice(&available[..amount]);
+        self.consume(amount);
+
+        Ok(amount)
+    }
+}
+
+impl<R: BufRead> BufRead for LineReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            // the bytes being consumed are still in the buffer of the inner reader
+            if let Ok(buffer) = self.inner.fill_buf() {
+                let newlines = buffer[..amt.min(buffer.len())]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, byte)| **byte == b'\n')
+                    .map(|(index, _)| self.consumed + index + 1);
+
+                self.line_starts.extend(newlines);
+            }
+        }
+
+        self.consumed += amt;
+        self.inner.consume(amt);
+    }
+}
+
+/// Options that change how [`Inkscape::parse_svg_with`](crate::Inkscape::parse_svg_with)
+/// reads a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    /// keep rectangles and images whose `id`, `width` or `height` are missing or can not
+    /// be parsed as plain xml, instead of failing to parse the document. Each of them is
+    /// reported as a [`ParseWarning`], just like an `x`, `y` or `transform` that can not be
+    /// parsed is in either mode
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    /// options for parsing in lenient mode, see [`ParseOptions::lenient`]
+    pub fn lenient() -> Self {
+        Self { lenient: true }
+    }
+}
+
+/// state shared by every step of parsing the layers of a document
+pub(crate) struct ParseState {
+    pub(crate) options: ParseOptions,
+    pub(crate) warnings: Vec<ParseWarning>,
+    /// the byte offset of the start of the last event read, which errors are reported at
+    pub(crate) event_start: usize,
+}
+
+impl ParseState {
+    pub(crate) fn new(options: ParseOptions) -> Self {
+        Self {
+            options,
+            warnings: Vec::new(),
+            event_start: 0,
+        }
+    }
+}
+
+/// read the next event, remembering where in the document it starts
+fn read_event<'b, R: BufRead>(
+    reader: &mut XmlReader<R>,
+    buffer: &'b mut Vec<u8>,
+    state: &mut ParseState,
+    phase: ParsePhase,
+) -> Result<Event<'b>, ReadXml> {
+    state.event_start = reader.buffer_position();
+    reader
+        .read_event_into(buffer)
+        .map_err(|err| ReadXml::new(err, phase))
+}
+
+/// attach the position of the last event that was read to an error
+pub(crate) fn error_at<R: BufRead>(
+    error: ParseLayer,
+    reader: &XmlReader<R>,
+    state: &ParseState,
+) -> ParseError {
+    ParseError::new(
+        Box::new(error),
+        reader.get_ref().position(state.event_start),
+    )
+}
 
 pub(crate) fn leading_events<R: BufRead>(
-    reader: &mut quick_xml::Reader<R>,
+    reader: &mut XmlReader<R>,
     buffer: &mut Vec<u8>,
-) -> (Vec<Event<'static>>, Option<BytesStart<'static>>) {
+    state: &mut ParseState,
+) -> Result<(Vec<Event<'static>>, Option<BytesStart<'static>>), ParseLayer> {
     let mut out = Vec::new();
 
-    while let Ok(event) = reader.read_event_into(buffer) {
-        let event = event.into_owned();
+    // how many elements deep we are in the document. Layers are only
+    // the <g> elements that are direct children of the root <svg> element,
+    // any <g> inside of <defs> (or similar) belongs to the leading events
+    let mut depth: usize = 0;
 
-        if let Event::Start(element) = event {
+    loop {
+        let event = read_event(reader, buffer, state, ParsePhase::Leading)?.into_owned();
+
+        match event {
             // if the name is starts a <g> tag then we
             // know that we are out of the leading events and are now in
             // the layer parsing, we need to return
-            if element.name() == QName(b"g") {
-                return (out, Some(element));
-            } else {
-                // we had a Event::Start(_) but it was not a starting
-                // event for <g>, so lets just put it back into Event::Start(_)
-                // and move on
+            Event::Start(element) if depth == 1 && element.name() == QName(b"g") => {
+                return Ok((out, Some(element)));
+            }
+            Event::Start(element) => {
+                depth += 1;
                 out.push(Event::Start(element));
             }
-        } else {
-            out.push(event);
+            Event::End(element) => {
+                depth = depth.saturating_sub(1);
+                out.push(Event::End(element));
+            }
+            Event::Eof => break,
+            other => out.push(other),
         }
     }
 
-    (out, None)
+    Ok((out, None))
 }
 
 pub(crate) fn trailing_events<R: BufRead>(
-    reader: &mut quick_xml::Reader<R>,
+    reader: &mut XmlReader<R>,
     buffer: &mut Vec<u8>,
     first_trailing_event: Event<'static>,
-) -> Vec<Event<'static>> {
+    state: &mut ParseState,
+) -> Result<Vec<Event<'static>>, ParseLayer> {
     let mut out = Vec::new();
 
     out.push(first_trailing_event);
-    while let Ok(event) = reader.read_event_into(buffer) {
+    loop {
+        let event = read_event(reader, buffer, state, ParsePhase::Trailing)?;
+
         if let Event::Eof = event {
             break;
         } else {
@@ -54,140 +224,306 @@ pub(crate) fn trailing_events<R: BufRead>(
         }
     }
 
-    out
+    Ok(out)
 }
 
 pub(crate) fn layers<R: BufRead>(
-    reader: &mut quick_xml::Reader<R>,
+    reader: &mut XmlReader<R>,
     buffer: &mut Vec<u8>,
     first_layer_start: BytesStart<'static>,
+    state: &mut ParseState,
 ) -> Result<(Vec<Layer>, Event<'static>), ParseLayer> {
     let mut out = Vec::new();
 
-    let first_group = group(first_layer_start, reader, buffer)?;
+    let first_group = layer(first_layer_start, reader, buffer, state)?;
     out.push(first_group);
 
-    while let Ok(event) = reader.read_event_into(buffer) {
-        let event = event.into_owned();
+    loop {
+        let event = read_event(reader, buffer, state, ParsePhase::Layers)?.into_owned();
 
-        if let Event::Start(element) = event {
+        match event {
             // if we are starting a new layer, then parse it
-            if element.name() == QName(b"g") {
-                let grp = group(element, reader, buffer)?;
+            Event::Start(element) if element.name() == QName(b"g") => {
+                let grp = layer(element, reader, buffer, state)?;
                 out.push(grp);
             }
             // otherwise, we have exhaused looking at all the layers
-            else {
-                return Ok((out, Event::Start(element)));
-            }
-        }
-        // if we have hit an end tag then we have reached the end of
-        // the layer list in another way, we also need to return
-        //
-        // this is probably a </svg> tag
-        else if let Event::End(end) = event {
-            if end.name() == QName(b"text") {
-                panic!("hit </text> ending element in svg parsing - this should not happen as it should be stored in objects for the layer");
-            } else {
+            Event::Start(element) => return Ok((out, Event::Start(element))),
+            // the only closing tag at this depth is the one of the root element, anything
+            // else belongs to an element that was never opened
+            Event::End(end) if end.name() == QName(b"svg") => {
                 return Ok((out, Event::End(end)));
             }
-            //println!("early exit from layers parsing for tag: {event:?}");
-            //break
+            Event::End(end) => {
+                let name = String::from_utf8_lossy(end.name().as_ref()).into_owned();
+                return Err(UnexpectedEndTag::new(name).into());
+            }
+            Event::Eof => break,
+            _ => (),
         }
     }
 
-    // only happens if our while let Ok(_) = loop ends with an error
-    // which requires some malformed xml
-    panic!("finished / errored on reading xml elements from inkscape document without returning correctly. Your inkscape document likely contains malformed xml");
+    // the document ended before the root element was closed
+    Err(UnexpectedEof.into())
 }
 
-/// parse all the contents (including header tag) of `<g> ... </g>` elements
-pub(crate) fn group<R: BufRead>(
+/// parse all the contents (including header tag) of a layer's `<g> ... </g>` elements
+pub(crate) fn layer<R: BufRead>(
     start_event: BytesStart<'static>,
-    reader: &mut quick_xml::Reader<R>,
+    reader: &mut XmlReader<R>,
     buffer: &mut Vec<u8>,
+    state: &mut ParseState,
 ) -> Result<Layer, ParseLayer> {
-    let id_attribute = start_event
-        .try_get_attribute(b"id")
-        .map_err(|_| MissingLayerId::new(start_event.clone()))?
-        .ok_or_else(|| MissingLayerId::new(start_event.clone()))?;
+    let id = layer_id(&start_event)?;
+    let name = layer_name(&start_event)?;
 
-    let id = String::from_utf8(id_attribute.value.to_vec())
-        .map_err(|_| MissingLayerId::new(start_event.clone()))?;
+    let (content, footer) = group_content(reader, buffer, &name, state)?;
 
-    let name = layer_name(&start_event)?;
+    let grp = Layer {
+        id,
+        name,
+        header: start_event,
+        content,
+        footer,
+    };
 
-    let mut content = Vec::new();
+    Ok(grp)
+}
 
-    let mut footer = None;
+/// parse all the contents (including header tag) of a plain `<g> ... </g>` group
+/// that is nested somewhere inside of the layer `layer_name`
+fn group<R: BufRead>(
+    start_event: BytesStart<'static>,
+    reader: &mut XmlReader<R>,
+    buffer: &mut Vec<u8>,
+    layer_name: &str,
+    state: &mut ParseState,
+) -> Result<object::Group, ParseLayer> {
+    let (content, footer) = group_content(reader, buffer, layer_name, state)?;
 
-    while let Ok(event) = reader.read_event_into(buffer) {
-        let event = event.into_owned();
+    Ok(object::Group {
+        header: start_event,
+        content,
+        footer,
+    })
+}
+
+/// parse the objects of a `<g>` element up to (and including) its closing `</g>`.
+///
+/// Nested `<g>` elements are parsed recursively as either sublayers or plain groups
+fn group_content<R: BufRead>(
+    reader: &mut XmlReader<R>,
+    buffer: &mut Vec<u8>,
+    layer_name: &str,
+    state: &mut ParseState,
+) -> Result<(Vec<object::Object>, Event<'static>), ParseLayer> {
+    let mut content = Vec::new();
+
+    loop {
+        let event = read_event(reader, buffer, state, ParsePhase::Layers)?.into_owned();
+        let position = reader.get_ref().position(state.event_start);
 
         match event {
             Event::Empty(xml_object) => {
                 // parse the object
-                let object = object(xml_object).map_err(|err| {
-                    let layer_name = layer_name(&start_event).unwrap();
-                    ParseObject::new(err, layer_name)
-                })?;
+                push_object(&mut content, xml_object, None, layer_name, position, state)?;
+            }
+            Event::Start(element)
+                if matches!(
+                    element.name(),
+                    QName(b"rect") | QName(b"image") | QName(b"text")
+                ) =>
+            {
+                // rectangles and images with <title> or <desc> children, and texts
+                let inner = element_content(reader, buffer, &element, layer_name)?;
+
+                push_object(
+                    &mut content,
+                    element,
+                    Some(inner),
+                    layer_name,
+                    position,
+                    state,
+                )?;
+            }
+            Event::Start(element) if element.name() == QName(b"g") => {
+                let object = if is_layer(&element) {
+                    object::Object::Layer(layer(element, reader, buffer, state)?)
+                } else {
+                    object::Object::Group(group(element, reader, buffer, layer_name, state)?)
+                };
 
                 content.push(object);
             }
             Event::End(end) if end.name() == QName(b"g") => {
-                footer = Some(Event::End(end));
-                break;
+                return Ok((content, Event::End(end)));
             }
+            Event::Eof => break,
             other_event => {
-                content.push(object::Object::Other(other_event));
+                content.push(object::Object::raw(other_event));
             }
         }
     }
 
-    let footer = if let Some(inner_footer) = footer {
-        inner_footer
-    } else {
-        let name = layer_name(&start_event)?;
-        return Err(MissingLayerEnd::new(name).into());
-    };
+    Err(MissingLayerEnd::new(layer_name.to_string()).into())
+}
 
-    let grp = Layer {
-        id,
-        name,
-        header: Event::Start(start_event),
-        content,
-        footer,
-    };
+/// parse an element of a layer and add it to `content`.
+///
+/// In lenient mode a rectangle or image without valid identifiers is added as plain
+/// events instead, and a warning is recorded. Positions and transforms that can not be
+/// parsed are recorded as warnings in either mode
+fn push_object(
+    content: &mut Vec<object::Object>,
+    element: BytesStart<'static>,
+    inner: Option<Vec<Event<'static>>>,
+    layer_name: &str,
+    position: TextPosition,
+    state: &mut ParseState,
+) -> Result<(), ParseLayer> {
+    let is_object = element.name() == QName(b"rect") || element.name() == QName(b"image");
+
+    if let (true, true, Err(error)) = (
+        state.options.lenient,
+        is_object,
+        object::Identifiers::parse(&element),
+    ) {
+        state
+            .warnings
+            .push(ParseWarning::new(error, layer_name.to_string(), position));
+
+        match inner {
+            Some(inner) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                let end = Event::End(BytesEnd::new(name));
+
+                content.push(object::Object::raw(Event::Start(element)));
+                content.extend(inner.into_iter().map(object::Object::raw));
+                content.push(object::Object::raw(end));
+            }
+            None => content.push(object::Object::raw(Event::Empty(element))),
+        }
 
-    Ok(grp)
+        return Ok(());
+    }
+
+    let (object, problems) = object_with_content(element, inner)
+        .map_err(|err| ParseObject::new(err, layer_name.to_string()))?;
+    content.push(object);
+
+    state.warnings.extend(
+        problems
+            .into_iter()
+            .map(|problem| ParseWarning::new(problem, layer_name.to_string(), position)),
+    );
+
+    Ok(())
+}
+
+/// read all events between `start` and its matching end tag. The end tag itself
+/// is consumed but not returned
+fn element_content<R: BufRead>(
+    reader: &mut XmlReader<R>,
+    buffer: &mut Vec<u8>,
+    start: &BytesStart<'static>,
+    layer_name: &str,
+) -> Result<Vec<Event<'static>>, ParseLayer> {
+    let mut content = Vec::new();
+    let mut depth: usize = 0;
+
+    loop {
+        // errors are reported at the start of the element, so the position is not updated
+        let event = reader
+            .read_event_into(buffer)
+            .map_err(|err| ReadXml::new(err, ParsePhase::Layers))?
+            .into_owned();
+
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) if depth == 0 => return Ok(content),
+            Event::End(_) => depth -= 1,
+            Event::Eof => break,
+            _ => (),
+        }
+
+        content.push(event);
+    }
+
+    let element_name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    Err(MissingObjectEnd::new(element_name, layer_name.to_string()).into())
+}
+
+/// check if a `<g>` element is an inkscape layer (`inkscape:groupmode="layer"`)
+/// instead of a plain group
+fn is_layer(element: &BytesStart<'static>) -> bool {
+    element
+        .attributes()
+        .filter_map(Result::ok)
+        .any(|att| att.key == QName(b"inkscape:groupmode") && att.value.as_ref() == b"layer")
 }
 
 /// map an element inside <g>... </g> to a `Object` that may be adjusted
 /// by the user
+#[cfg(test)]
 pub(crate) fn object(element: BytesStart<'static>) -> Result<object::Object, IdentifierError> {
+    object_with_content(element, None).map(|(object, _problems)| object)
+}
+
+/// same as [`object`], but `content` contains the child events if the element
+/// was written as a start / end pair. Along with the object come the attributes that fell
+/// back to their default, see [`object::Identifiers::parse`]
+fn object_with_content(
+    element: BytesStart<'static>,
+    content: Option<Vec<Event<'static>>>,
+) -> Result<(object::Object, Vec<IdentifierError>), IdentifierError> {
     let obj = match element.name() {
         QName(b"image") => {
             // parse as an image
-            let ident = object::Identifiers::from_elem(&element)?;
-
-            object::Object::Image(object::Image { ident, element })
+            let (ident, problems) = object::Identifiers::parse(&element)?;
+
+            let image = object::Object::Image(object::Image {
+                ident,
+                element,
+                content,
+            });
+            (image, problems)
         }
         QName(b"rect") => {
             // parse as a rectangle
-            let ident = object::Identifiers::from_elem(&element)?;
-
-            object::Object::Rectangle(object::Rectangle { ident, element })
+            let (ident, problems) = object::Identifiers::parse(&element)?;
+
+            let rect = object::Object::Rectangle(object::Rectangle {
+                ident,
+                element,
+                content,
+            });
+            (rect, problems)
         }
-        _unknown => object::Object::Other(Event::Empty(element)),
+        QName(b"text") => (
+            object::Object::Text(Text::new(element, content)),
+            Vec::new(),
+        ),
+        _unknown => (object::Object::raw(Event::Empty(element)), Vec::new()),
     };
 
     Ok(obj)
 }
 
-fn layer_name(layer_start_event: &BytesStart<'static>) -> Result<String, MissingLayerName> {
+pub(crate) fn layer_id(start_event: &BytesStart<'static>) -> Result<String, MissingLayerId> {
+    let id_attribute = start_event
+        .try_get_attribute(b"id")
+        .map_err(|_| MissingLayerId::new(start_event.clone()))?
+        .ok_or_else(|| MissingLayerId::new(start_event.clone()))?;
+
+    String::from_utf8(id_attribute.value.to_vec())
+        .map_err(|_| MissingLayerId::new(start_event.clone()))
+}
+
+pub(crate) fn layer_name(
+    layer_start_event: &BytesStart<'static>,
+) -> Result<String, MissingLayerName> {
     let (_, name_id) = layer_start_event
         .attributes()
-        .into_iter()
         .filter_map(|x| x.ok())
         .map(|att| (att.key, att.value))
         .find(|(key, _)| key == &QName(b"inkscape:label"))
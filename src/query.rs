@@ -0,0 +1,560 @@
+use super::error::*;
+use super::object;
+use super::object::Object;
+use super::Layer;
+
+use quick_xml::events::BytesStart;
+
+use std::str::FromStr;
+
+/// A CSS-like selector that finds layers and objects by element name, id, class, attributes
+/// and ancestry, such as `layer[label="plots"] rect[data-role=placeholder]`.
+/// See [`Inkscape::select`](crate::Inkscape::select)
+///
+/// Supported are
+/// - element names (`rect`, `g`, `*`), where `layer` matches only layers
+/// - `#id` and `.class`
+/// - `[name]`, `[name=value]`, `[name~=value]`, `[name^=value]`, `[name$=value]` and
+///   `[name*=value]`, where `label` is short for `inkscape:label`
+/// - the descendant (`a b`) and child (`a > b`) combinators
+/// - lists of selectors separated by `,`
+///
+/// Ancestry only covers the layers and groups of the document, not the root `<svg>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    /// the comma separated selectors, any of which may match
+    selectors: Vec<Vec<Part>>,
+}
+
+/// a compound selector along with how it relates to the one before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Part {
+    compound: Compound,
+    /// `None` for the first part of a selector
+    combinator: Option<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+/// everything a single layer or object has to match, such as `rect.panel[width]`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Compound {
+    /// `None` for `*` or when no element name is given
+    name: Option<String>,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    Id(String),
+    Class(String),
+    Attribute { name: String, operator: Operator },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operator {
+    Exists,
+    Equals(String),
+    /// one of the whitespace separated words of the value
+    Includes(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+}
+
+/// A layer or object picked out by a [`Query`], identified by its position in the document
+/// so that objects without an id are found as well
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Handle {
+    /// the index of the top level layer, followed by the index of the object in every
+    /// layer or group below it
+    path: Vec<usize>,
+    id: Option<String>,
+}
+
+impl Handle {
+    /// the id of the layer or object, to use with methods that take an id such as
+    /// [`Inkscape::id_to_image`](crate::Inkscape::id_to_image)
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// whether the handle is a top level layer rather than an object inside of one
+    pub fn is_layer(&self) -> bool {
+        self.path.len() == 1
+    }
+}
+
+/// A layer or object picked out by a [`Query`], see
+/// [`Inkscape::select_mut`](crate::Inkscape::select_mut)
+#[derive(Debug)]
+pub enum SelectedMut<'a> {
+    /// a top level layer
+    Layer(&'a mut Layer),
+    /// an object inside of a layer, which may itself be a sublayer
+    Object(&'a mut Object),
+}
+
+/// a layer or object being matched against a query
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    element: &'a BytesStart<'static>,
+    is_layer: bool,
+}
+
+impl Query {
+    /// whether the node matches any of the selectors, given its ancestors from the
+    /// outermost layer inwards
+    fn matches(&self, node: Node<'_>, ancestors: &[Node<'_>]) -> bool {
+        self.selectors
+            .iter()
+            .any(|parts| matches_parts(parts, node, ancestors))
+    }
+
+    /// the handles of every layer and object matching the query, depth first
+    pub(crate) fn select(&self, layers: &[Layer]) -> Vec<Handle> {
+        let mut found = Vec::new();
+        let mut ancestors = Vec::new();
+        let mut path = Vec::new();
+
+        for (index, layer) in layers.iter().enumerate() {
+            path.push(index);
+            self.select_layer(layer, &mut ancestors, &mut path, &mut found);
+            path.pop();
+        }
+
+        found
+    }
+
+    fn select_layer<'a>(
+        &self,
+        layer: &'a Layer,
+        ancestors: &mut Vec<Node<'a>>,
+        path: &mut Vec<usize>,
+        found: &mut Vec<Handle>,
+    ) {
+        let node = Node {
+            element: &layer.header,
+            is_layer: true,
+        };
+        if self.matches(node, ancestors) {
+            found.push(Handle {
+                path: path.clone(),
+                id: Some(layer.id.clone()),
+            });
+        }
+
+        ancestors.push(node);
+        self.select_objects(&layer.content, ancestors, path, found);
+        ancestors.pop();
+    }
+
+    fn select_objects<'a>(
+        &self,
+        content: &'a [Object],
+        ancestors: &mut Vec<Node<'a>>,
+        path: &mut Vec<usize>,
+        found: &mut Vec<Handle>,
+    ) {
+        // how deep we are inside of raw xml elements, whose raw content belongs to them
+        let mut depth: usize = 0;
+
+        for (index, object) in content.iter().enumerate() {
+            let nested = depth > 0;
+            depth = depth.saturating_add_signed(object.raw_nesting());
+            if nested && matches!(object, Object::Other(_)) {
+                continue;
+            }
+
+            path.push(index);
+
+            match object {
+                Object::Layer(layer) => self.select_layer(layer, ancestors, path, found),
+                object => {
+                    // whitespace, comments and closing tags are not objects of their own
+                    if let Some(element) = object.start_element() {
+                        let node = Node {
+                            element,
+                            is_layer: false,
+                        };
+                        if self.matches(node, ancestors) {
+                            found.push(Handle {
+                                path: path.clone(),
+                                id: object.id(),
+                            });
+                        }
+
+                        if let Some(children) = object.children() {
+                            ancestors.push(node);
+                            self.select_objects(children, ancestors, path, found);
+                            ancestors.pop();
+                        }
+                    }
+                }
+            }
+
+            path.pop();
+        }
+    }
+}
+
+/// the layer or object at `handle`, if the document still has one there
+pub(crate) fn resolve_mut<'a>(layers: &'a mut [Layer], handle: &Handle) -> Option<SelectedMut<'a>> {
+    let (first, rest) = handle.path.split_first()?;
+    let layer = layers.get_mut(*first)?;
+
+    let (last, middle) = match rest.split_last() {
+        Some(split) => split,
+        None => return Some(SelectedMut::Layer(layer)),
+    };
+
+    let mut content = &mut layer.content;
+    for index in middle {
+        content = content.get_mut(*index)?.children_mut()?;
+    }
+
+    content.get_mut(*last).map(SelectedMut::Object)
+}
+
+/// whether `node` matches the last part of a selector, and its ancestors the parts before it
+fn matches_parts(parts: &[Part], node: Node<'_>, ancestors: &[Node<'_>]) -> bool {
+    let (last, rest) = match parts.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    if !last.compound.matches(node) {
+        return false;
+    }
+
+    match last.combinator {
+        None => true,
+        Some(Combinator::Child) => match ancestors.split_last() {
+            Some((parent, above)) => matches_parts(rest, *parent, above),
+            None => false,
+        },
+        Some(Combinator::Descendant) => (0..ancestors.len())
+            .rev()
+            .any(|index| matches_parts(rest, ancestors[index], &ancestors[..index])),
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: Node<'_>) -> bool {
+        let name_matches = match self.name.as_deref() {
+            None => true,
+            Some("layer") => node.is_layer,
+            Some(name) => node.element.name().as_ref() == name.as_bytes(),
+        };
+
+        name_matches
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(node.element))
+    }
+}
+
+impl Condition {
+    fn matches(&self, element: &BytesStart<'_>) -> bool {
+        match self {
+            Self::Id(id) => object::attribute_value(element, b"id").as_deref() == Some(id),
+            Self::Class(class) => object::attribute_value(element, b"class")
+                .is_some_and(|classes| classes.split_whitespace().any(|c| c == class)),
+            Self::Attribute { name, operator } => {
+                let value = object::attribute_value(element, name.as_bytes()).or_else(|| {
+                    // `label` is short for the label inkscape shows in the Objects panel
+                    (name == "label")
+                        .then(|| object::attribute_value(element, b"inkscape:label"))
+                        .flatten()
+                });
+
+                match value {
+                    Some(value) => operator.matches(&value),
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+impl Operator {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Exists => true,
+            Self::Equals(expected) => value == expected,
+            Self::Includes(word) => value.split_whitespace().any(|w| w == word),
+            Self::Prefix(prefix) => !prefix.is_empty() && value.starts_with(prefix.as_str()),
+            Self::Suffix(suffix) => !suffix.is_empty() && value.ends_with(suffix.as_str()),
+            Self::Contains(part) => !part.is_empty() && value.contains(part.as_str()),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseQueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            query: s,
+            chars: s.char_indices().peekable(),
+        };
+
+        let mut selectors = vec![parser.selector()?];
+        while parser.eat(',') {
+            selectors.push(parser.selector()?);
+        }
+
+        match parser.chars.peek() {
+            None => Ok(Self { selectors }),
+            Some(&(position, _)) => Err(parser.error(position, "unexpected character")),
+        }
+    }
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn error(&self, position: usize, reason: &'static str) -> ParseQueryError {
+        ParseQueryError::new(self.query.to_string(), position, reason)
+    }
+
+    /// the position of the next character, or the end of the query
+    fn position(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.query.len(), |&(position, _)| position)
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {
+            skipped = true;
+        }
+        skipped
+    }
+
+    /// skip over `c` (and whitespace around it) if it is next
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        let eaten = self.chars.next_if(|&(_, next)| next == c).is_some();
+        if eaten {
+            self.skip_whitespace();
+        }
+        eaten
+    }
+
+    /// a selector made of compound selectors and the combinators between them
+    fn selector(&mut self) -> Result<Vec<Part>, ParseQueryError> {
+        self.skip_whitespace();
+
+        let mut parts = vec![Part {
+            compound: self.compound()?,
+            combinator: None,
+        }];
+
+        loop {
+            let whitespace = self.skip_whitespace();
+
+            let combinator = match self.chars.peek() {
+                Some((_, '>')) => {
+                    self.chars.next();
+                    self.skip_whitespace();
+                    Combinator::Child
+                }
+                Some((_, ',')) | None => break,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(&(position, _)) => return Err(self.error(position, "unexpected character")),
+            };
+
+            parts.push(Part {
+                compound: self.compound()?,
+                combinator: Some(combinator),
+            });
+        }
+
+        Ok(parts)
+    }
+
+    fn compound(&mut self) -> Result<Compound, ParseQueryError> {
+        let start = self.position();
+        let mut compound = Compound::default();
+
+        if self.chars.next_if(|&(_, c)| c == '*').is_none() {
+            let name = self.name();
+            if !name.is_empty() {
+                compound.name = Some(name);
+            }
+        }
+
+        loop {
+            match self.chars.peek() {
+                Some((_, '#')) => {
+                    self.chars.next();
+                    compound
+                        .conditions
+                        .push(Condition::Id(self.required_name()?));
+                }
+                Some((_, '.')) => {
+                    self.chars.next();
+                    compound
+                        .conditions
+                        .push(Condition::Class(self.required_name()?));
+                }
+                Some((_, '[')) => {
+                    self.chars.next();
+                    compound.conditions.push(self.attribute()?);
+                }
+                _ => break,
+            }
+        }
+
+        // `*` on its own is a valid compound selector, but nothing at all is not
+        if self.position() == start {
+            return Err(self.error(start, "expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    /// the inside of `[name op value]`, after the `[`
+    fn attribute(&mut self) -> Result<Condition, ParseQueryError> {
+        self.skip_whitespace();
+        let name = self.required_name()?;
+        self.skip_whitespace();
+
+        let operator: fn(String) -> Operator = match self.chars.next() {
+            Some((_, ']')) => {
+                return Ok(Condition::Attribute {
+                    name,
+                    operator: Operator::Exists,
+                })
+            }
+            Some((_, '=')) => Operator::Equals,
+            Some((position, c @ ('~' | '^' | '$' | '*'))) => {
+                if self.chars.next_if(|&(_, next)| next == '=').is_none() {
+                    return Err(self.error(position, "expected `=` after the operator"));
+                }
+                match c {
+                    '~' => Operator::Includes,
+                    '^' => Operator::Prefix,
+                    '$' => Operator::Suffix,
+                    _ => Operator::Contains,
+                }
+            }
+            Some((position, _)) => return Err(self.error(position, "expected `]` or `=`")),
+            None => return Err(self.error(self.query.len(), "unclosed `[`")),
+        };
+
+        self.skip_whitespace();
+        let value = self.value()?;
+        self.skip_whitespace();
+
+        match self.chars.next() {
+            Some((_, ']')) => Ok(Condition::Attribute {
+                name,
+                operator: operator(value),
+            }),
+            Some((position, _)) => Err(self.error(position, "expected `]`")),
+            None => Err(self.error(self.query.len(), "unclosed `[`")),
+        }
+    }
+
+    /// a quoted string or a name
+    fn value(&mut self) -> Result<String, ParseQueryError> {
+        let quote = match self.chars.next_if(|&(_, c)| c == '"' || c == '\'') {
+            Some((_, quote)) => quote,
+            None => return self.required_name(),
+        };
+
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, c)) if c == quote => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                Some((_, c)) => value.push(c),
+                None => break,
+            }
+        }
+
+        Err(self.error(self.query.len(), "unclosed quote"))
+    }
+
+    /// an element or attribute name, id or class, which may be empty
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|&(_, c)| c.is_alphanumeric() || matches!(c, '-' | '_' | ':'))
+        {
+            name.push(c);
+        }
+        name
+    }
+
+    fn required_name(&mut self) -> Result<String, ParseQueryError> {
+        let position = self.position();
+        let name = self.name();
+        if name.is_empty() {
+            return Err(self.error(position, "expected a name"));
+        }
+        Ok(name)
+    }
+}
+
+#[test]
+fn parse_queries() {
+    let query: Query = r#"layer[label="plots"] > rect.panel[data-role=placeholder], #logo"#
+        .parse()
+        .unwrap();
+
+    assert_eq!(query.selectors.len(), 2);
+    let parts = &query.selectors[0];
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].compound.name.as_deref(), Some("layer"));
+    assert_eq!(
+        parts[0].compound.conditions,
+        [Condition::Attribute {
+            name: "label".into(),
+            operator: Operator::Equals("plots".into()),
+        }]
+    );
+    assert_eq!(parts[1].combinator, Some(Combinator::Child));
+    assert_eq!(
+        parts[1].compound.conditions,
+        [
+            Condition::Class("panel".into()),
+            Condition::Attribute {
+                name: "data-role".into(),
+                operator: Operator::Equals("placeholder".into()),
+            }
+        ]
+    );
+    assert_eq!(
+        query.selectors[1][0].compound.conditions,
+        [Condition::Id("logo".into())]
+    );
+
+    for invalid in [
+        "",
+        "rect[",
+        "rect[width",
+        "rect >",
+        "a, ",
+        "#",
+        "rect[x|=1]",
+        "g ) rect",
+    ] {
+        assert!(invalid.parse::<Query>().is_err(), "{invalid:?} parsed");
+    }
+}
@@ -0,0 +1,101 @@
+use super::error::*;
+use super::object::Object;
+
+/// Picks out objects by their `id` or by the `inkscape:label` set in the Objects panel of
+/// inkscape, see [`Inkscape::select_id`](crate::Inkscape::select_id)
+#[derive(Debug, Clone)]
+pub enum Selector {
+    /// the object with exactly this id
+    Id(String),
+    /// objects with exactly this label
+    Label(String),
+    /// objects whose id or label matches a regular expression, see [`Selector::glob`]
+    /// and [`Selector::regex`]
+    Pattern(regex::Regex),
+}
+
+impl Selector {
+    pub fn id<T: Into<String>>(id: T) -> Self {
+        Self::Id(id.into())
+    }
+
+    pub fn label<T: Into<String>>(label: T) -> Self {
+        Self::Label(label.into())
+    }
+
+    /// objects whose whole id or label matches a glob pattern such as `panel_*`, where `*`
+    /// matches any characters and `?` a single one
+    pub fn glob(pattern: &str) -> Result<Self, InvalidPattern> {
+        let mut regex = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                other => regex.push_str(&regex::escape(other.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        regex.push('$');
+
+        Self::regex(&regex)
+    }
+
+    /// objects whose id or label contains a match of a regular expression. Use `^` and `$`
+    /// to match the whole id or label
+    pub fn regex(pattern: &str) -> Result<Self, InvalidPattern> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|error| InvalidPattern::new(pattern.into(), error))?;
+
+        Ok(Self::Pattern(regex))
+    }
+
+    /// whether `object` is picked out by the selector
+    pub fn matches(&self, object: &Object) -> bool {
+        match self {
+            Self::Id(id) => object.id().as_deref() == Some(id),
+            Self::Label(label) => object.label().as_deref() == Some(label),
+            Self::Pattern(regex) => object
+                .id()
+                .into_iter()
+                .chain(object.label())
+                .any(|value| regex.is_match(&value)),
+        }
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "id `{id}`"),
+            Self::Label(label) => write!(f, "label `{label}`"),
+            Self::Pattern(regex) => write!(f, "pattern `{regex}`"),
+        }
+    }
+}
+
+/// What happened when filling placeholders with
+/// [`Inkscape::fill_matching`](crate::Inkscape::fill_matching)
+#[derive(Debug, Clone, Default)]
+pub struct FillReport {
+    /// the ids of every placeholder that was filled, in the order they were filled
+    pub filled: Vec<String>,
+    /// selectors that did not match any rectangle or image, even if they matched other
+    /// objects such as texts or groups
+    pub unmatched: Vec<Selector>,
+    /// placeholders that were matched but could not be filled
+    pub failed: Vec<MissingId>,
+}
+
+#[test]
+fn glob_patterns() {
+    let selector = Selector::glob("panel_?.*").unwrap();
+
+    let Selector::Pattern(regex) = &selector else {
+        panic!("glob was not compiled to a pattern");
+    };
+    assert!(regex.is_match("panel_a.png"));
+    assert!(!regex.is_match("panel_ab.png"));
+    assert!(!regex.is_match("panel_a-png"));
+    assert!(!regex.is_match("main_panel_a.png"));
+
+    assert!(Selector::regex("panel_(").is_err());
+}
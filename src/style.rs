@@ -0,0 +1,151 @@
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// The CSS declarations of a `style` attribute, such as `fill:#ff0000;opacity:0.5`
+///
+/// Declarations keep the order they were written in, so that an unchanged style is written
+/// back out exactly as it was read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    declarations: Vec<(String, String)>,
+}
+
+impl Style {
+    /// the value of a property, if it is declared
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.declarations
+            .iter()
+            .find(|(name, _)| name == property)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// set the value of a property, replacing the existing declaration if there is one
+    pub fn set<P: Into<String>, V: Into<String>>(&mut self, property: P, value: V) {
+        let property = property.into();
+        let value = value.into();
+
+        if let Some((_, existing)) = self
+            .declarations
+            .iter_mut()
+            .find(|(name, _)| *name == property)
+        {
+            *existing = value;
+        } else {
+            self.declarations.push((property, value));
+        }
+    }
+
+    /// remove a property, returning its value if it was declared
+    pub fn remove(&mut self, property: &str) -> Option<String> {
+        let index = self
+            .declarations
+            .iter()
+            .position(|(name, _)| name == property)?;
+
+        Some(self.declarations.remove(index).1)
+    }
+
+    /// all `(property, value)` declarations in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.declarations
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
+}
+
+impl FromStr for Style {
+    type Err = Infallible;
+
+    /// parse a style attribute. Declarations without a `:` are ignored, the same way
+    /// a browser would ignore them
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let declarations = split_declarations(s)
+            .filter_map(|declaration| {
+                let (name, value) = declaration.split_once(':')?;
+                let name = name.trim();
+
+                if name.is_empty() {
+                    return None;
+                }
+
+                Some((name.to_string(), value.trim().to_string()))
+            })
+            .collect();
+
+        Ok(Self { declarations })
+    }
+}
+
+impl std::fmt::Display for Style {
+    /// write the declarations the same way inkscape does: `name:value;name:value`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, value)) in self.declarations.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+
+            write!(f, "{name}:{value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// split on the `;` between declarations, ignoring any inside of quotes or parentheses
+/// (`font-family:'a;b'` or `fill:url(data:...;base64,...)`)
+fn split_declarations(s: &str) -> impl Iterator<Item = &str> {
+    let mut out = Vec::new();
+    let mut quote = None;
+    let mut parens = 0usize;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('(', None) => parens += 1,
+            (')', None) => parens = parens.saturating_sub(1),
+            (';', None) if parens == 0 => {
+                out.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    out.push(&s[start..]);
+
+    out.into_iter()
+}
+
+#[test]
+fn parse_and_edit_style() {
+    let mut style: Style = "fill:#34ff14; fill-opacity:1;stroke-width:0.449059;"
+        .parse()
+        .unwrap();
+
+    assert_eq!(style.get("fill"), Some("#34ff14"));
+    assert_eq!(style.get("fill-opacity"), Some("1"));
+    assert_eq!(style.get("display"), None);
+
+    style.set("fill", "#000000");
+    style.set("display", "none");
+    assert_eq!(style.remove("fill-opacity").as_deref(), Some("1"));
+
+    assert_eq!(
+        style.to_string(),
+        "fill:#000000;stroke-width:0.449059;display:none"
+    );
+
+    let style: Style = "font-family:'a;b';fill:url(#p;1);broken".parse().unwrap();
+    assert_eq!(
+        style.iter().collect::<Vec<_>>(),
+        [("font-family", "'a;b'"), ("fill", "url(#p;1)")]
+    );
+
+    assert!("".parse::<Style>().unwrap().is_empty());
+}
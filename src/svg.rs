@@ -0,0 +1,289 @@
+use super::error::*;
+use super::ids;
+use super::object;
+use super::units::Viewport;
+
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
+use quick_xml::events::Event;
+use quick_xml::name::QName;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::BufRead;
+use std::path::Path;
+
+/// How the content of an [`SvgDocument`] is fit into a placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SvgPlacement {
+    /// wrap the content in a `<g transform="...">` that scales it uniformly to fit inside
+    /// the placeholder, centered in both directions
+    #[default]
+    Transform,
+    /// wrap the content in a nested `<svg viewBox="...">` and let the renderer fit it
+    /// inside of the placeholder
+    NestedSvg,
+}
+
+/// Another svg document (for example a matplotlib or plotters export) that can replace a
+/// placeholder with [`Inkscape::id_to_svg`](crate::Inkscape::id_to_svg)
+#[derive(Debug)]
+pub struct SvgDocument {
+    /// the root `<svg>` element of the document
+    root: BytesStart<'static>,
+    /// the coordinate system of the root element
+    viewport: Viewport,
+    /// everything between `<svg>` and `</svg>` of the root element
+    content: Vec<Event<'static>>,
+}
+
+impl SvgDocument {
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Result<Self, SvgError> {
+        let path = path.as_ref();
+
+        let file = std::fs::File::open(path).map_err(|err| OpenFile::new(err, path.to_owned()))?;
+
+        Self::from_reader(std::io::BufReader::new(file))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SvgError> {
+        Self::from_reader(bytes)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, SvgError> {
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        let mut buffer = Vec::new();
+
+        // skip over the xml declaration, doctype and comments until we reach the root element
+        let root = loop {
+            match reader.read_event_into(&mut buffer).map_err(ReadSvg::new)? {
+                Event::Start(element) if element.name() == QName(b"svg") => {
+                    break element.into_owned()
+                }
+                Event::Eof => return Err(MissingSvgRoot.into()),
+                _ => (),
+            }
+        };
+
+        let mut content = Vec::new();
+        let mut depth: usize = 0;
+
+        loop {
+            let event = reader.read_event_into(&mut buffer).map_err(ReadSvg::new)?;
+
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => break,
+                Event::End(_) => depth -= 1,
+                Event::Eof => return Err(MissingSvgRoot.into()),
+                _ => (),
+            }
+
+            content.push(event.into_owned());
+        }
+
+        let viewport = Viewport::from_root(&root)?;
+
+        Ok(Self {
+            root,
+            viewport,
+            content,
+        })
+    }
+
+    /// build the object that replaces a placeholder element
+    ///
+    /// `placeholder` is the `<rect>` or `<image>` element being replaced, `ident` its
+    /// parsed identifiers and `host` the viewport of the document it is in.
+    /// All ids in the document are prefixed with the placeholder id.
+    pub(crate) fn into_object(
+        mut self,
+        placeholder: &BytesStart<'static>,
+        ident: &object::Identifiers,
+        host: &Viewport,
+        placement: SvgPlacement,
+    ) -> Result<object::Object, EmbedSvgError> {
+        let (x, y) = ident.user_position(host);
+        let (placeholder_width, placeholder_height) = ident.user_dimensions(host);
+
+        prefix_ids(&mut self.content, &ident.id);
+
+        // attributes carried over from the placeholder to the new element
+        let transform = object::attribute_value(placeholder, b"transform");
+        let label = object::attribute_value(placeholder, b"inkscape:label");
+
+        // namespace declarations (xmlns:xlink especially) that the content may rely on
+        let namespaces = self
+            .root
+            .attributes()
+            .filter_map(Result::ok)
+            .filter(|att| att.key.as_ref().starts_with(b"xmlns:"))
+            .collect::<Vec<_>>();
+
+        let [min_x, min_y, width, height] = self.viewport.view_box();
+
+        let mut content: Vec<object::Object> = Vec::new();
+
+        let header = match placement {
+            SvgPlacement::Transform => {
+                // uniformly scale and center the content the same way
+                // `preserveAspectRatio="xMidYMid meet"` would
+                let scale = (placeholder_width / width).min(placeholder_height / height);
+                let offset_x = x + (placeholder_width - width * scale) / 2.0;
+                let offset_y = y + (placeholder_height - height * scale) / 2.0;
+
+                let mut fit = format!("translate({offset_x},{offset_y}) scale({scale})");
+                if min_x != 0.0 || min_y != 0.0 {
+                    write!(fit, " translate({},{})", -min_x, -min_y).unwrap();
+                }
+                let transform = match transform {
+                    Some(transform) => format!("{transform} {fit}"),
+                    None => fit,
+                };
+
+                let mut header = BytesStart::new("g");
+                header.push_attribute(("id", ident.id.as_str()));
+                if let Some(label) = &label {
+                    header.push_attribute(("inkscape:label", label.as_str()));
+                }
+                header.push_attribute(("transform", transform.as_str()));
+                header.extend_attributes(namespaces);
+
+                content.extend(self.content.into_iter().map(object::Object::raw));
+
+                header
+            }
+            SvgPlacement::NestedSvg => {
+                let mut svg = BytesStart::new("svg");
+                svg.push_attribute(("x", x.to_string().as_str()));
+                svg.push_attribute(("y", y.to_string().as_str()));
+                svg.push_attribute(("width", placeholder_width.to_string().as_str()));
+                svg.push_attribute(("height", placeholder_height.to_string().as_str()));
+                svg.push_attribute((
+                    "viewBox",
+                    format!("{min_x} {min_y} {width} {height}").as_str(),
+                ));
+                if let Some(aspect) = object::attribute_value(&self.root, b"preserveAspectRatio") {
+                    svg.push_attribute(("preserveAspectRatio", aspect.as_str()));
+                }
+                svg.extend_attributes(namespaces);
+
+                // <svg> elements can not be transformed in SVG 1.1, so the id, label and
+                // transform of the placeholder are kept on a group around it
+                let mut header = BytesStart::new("g");
+                header.push_attribute(("id", ident.id.as_str()));
+                if let Some(label) = &label {
+                    header.push_attribute(("inkscape:label", label.as_str()));
+                }
+                if let Some(transform) = &transform {
+                    header.push_attribute(("transform", transform.as_str()));
+                }
+
+                content.push(object::Object::raw(Event::Start(svg)));
+                content.extend(self.content.into_iter().map(object::Object::raw));
+                content.push(object::Object::raw(Event::End(BytesEnd::new("svg"))));
+
+                header
+            }
+        };
+
+        Ok(object::Object::Group(object::Group {
+            header,
+            content,
+            footer: Event::End(BytesEnd::new("g")),
+        }))
+    }
+}
+
+/// prefix every id defined in `events` with `{id}-`, along with every reference to those
+/// ids (`href="#id"`, `url(#id)`, `#id` selectors) so they can not collide with ids of the
+/// host document. The rules of `<style>` elements are limited to the element with id `id`
+/// that holds the events, so that they do not apply to the rest of the host document.
+///
+/// `<rect>` and `<image>` elements without an id are given one, since the document
+/// they are embedded in could not be parsed again otherwise
+fn prefix_ids(events: &mut [Event<'static>], id: &str) {
+    let prefix = format!("{id}-");
+
+    let renames = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(element) | Event::Empty(element) => {
+                object::attribute_value(element, b"id")
+            }
+            _ => None,
+        })
+        .map(|id| {
+            let prefixed = format!("{prefix}{id}");
+            (id, prefixed)
+        })
+        .collect::<HashMap<_, _>>();
+
+    // whether we are inside of a <style> element, whose text is a stylesheet
+    let mut in_style = false;
+
+    // counter for generating ids of elements that do not have one
+    let mut generated = 0;
+
+    for event in events.iter_mut() {
+        ids::rename_in_style(event, &mut in_style, &renames, Some(id));
+
+        match event {
+            Event::Start(element) | Event::Empty(element) => {
+                ids::rename_in_element(element, &renames);
+
+                let needs_id =
+                    element.name() == QName(b"rect") || element.name() == QName(b"image");
+                if needs_id && object::attribute_value(element, b"id").is_none() {
+                    let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                    element.push_attribute(("id", format!("{prefix}{name}{generated}").as_str()));
+                    generated += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn prefix_references() {
+    let svg = r##"<?xml version="1.0" encoding="utf-8" standalone="no"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<svg xmlns:xlink="http://www.w3.org/1999/xlink" width="460.8pt" height="345.6pt" viewBox="0 0 460.8 345.6" xmlns="http://www.w3.org/2000/svg" version="1.1">
+ <defs>
+  <style type="text/css">*{stroke-linejoin: round} #patch_1 {fill: url(#p1)}</style>
+  <clipPath id="p1"><rect x="57.6" y="41.472" width="357.12" height="266.112"/></clipPath>
+ </defs>
+ <g id="figure_1">
+  <path id="m0" d="M 0 0 L 0 3.5"/>
+  <path id="patch_1" d="M 57.6 307.584 L 414.72 307.584"/>
+  <use xlink:href="#m0" x="73.832727" y="307.584" clip-path="url(#p1)"/>
+  <use xlink:href="#not_in_document" x="0" y="0"/>
+ </g>
+</svg>"##;
+
+    let mut svg = SvgDocument::from_bytes(svg.as_bytes()).unwrap();
+    assert_eq!(svg.viewport.view_box(), [0.0, 0.0, 460.8, 345.6]);
+
+    prefix_ids(&mut svg.content, "rect286");
+
+    let mut output = Vec::new();
+    let mut writer = quick_xml::Writer::new(&mut output);
+    for event in &svg.content {
+        writer.write_event(event).unwrap();
+    }
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains(r#"<clipPath id="rect286-p1">"#));
+    assert!(output.contains(r#"height="266.112" id="rect286-rect0"/>"#));
+    assert!(output.contains(r#"<g id="rect286-figure_1">"#));
+    assert!(output.contains(r##"xlink:href="#rect286-m0""##));
+    assert!(output.contains(r#"clip-path="url(#rect286-p1)""#));
+    assert!(output.contains(r#"<path id="rect286-patch_1""#));
+    // the rules of the stylesheet only apply inside of the placeholder
+    assert!(output.contains(
+        r#"#rect286 *{stroke-linejoin: round} #rect286 #rect286-patch_1 {fill: url(#rect286-p1)}"#
+    ));
+    // references to ids outside of the embedded document are left alone
+    assert!(output.contains(r##"xlink:href="#not_in_document""##));
+}
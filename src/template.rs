@@ -0,0 +1,189 @@
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::BytesCData;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// What happened when filling the `{{variable}}` placeholders of a document with
+/// [`Inkscape::fill_template`](crate::Inkscape::fill_template)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateReport {
+    /// placeholders in the document without a value, which were left as they are
+    pub unresolved: Vec<String>,
+    /// values that did not match any placeholder in the document
+    pub unused: Vec<String>,
+}
+
+impl TemplateReport {
+    /// whether every placeholder had a value and every value was used
+    pub fn is_complete(&self) -> bool {
+        self.unresolved.is_empty() && self.unused.is_empty()
+    }
+}
+
+/// replaces placeholders with values, keeping track of which ones were used
+pub(crate) struct Substitution<'a> {
+    values: &'a HashMap<String, String>,
+    used: BTreeSet<&'a str>,
+    unresolved: BTreeSet<String>,
+}
+
+impl<'a> Substitution<'a> {
+    pub(crate) fn new(values: &'a HashMap<String, String>) -> Self {
+        Self {
+            values,
+            used: BTreeSet::new(),
+            unresolved: BTreeSet::new(),
+        }
+    }
+
+    /// replace the placeholders in the attribute values of an element
+    pub(crate) fn element(&mut self, element: &mut BytesStart<'static>) {
+        let mut changed = false;
+
+        let attributes = element
+            .attributes()
+            .filter_map(Result::ok)
+            .map(|att| {
+                let new_value = att
+                    .unescape_value()
+                    .ok()
+                    .and_then(|value| self.replace(&value));
+
+                match new_value {
+                    Some(new_value) => {
+                        changed = true;
+                        let value = quick_xml::escape::escape(&new_value)
+                            .into_owned()
+                            .into_bytes();
+                        Attribute {
+                            key: att.key,
+                            value: Cow::Owned(value),
+                        }
+                    }
+                    None => att,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if changed {
+            let mut new_element = element.to_owned();
+            new_element.clear_attributes();
+            new_element.extend_attributes(attributes);
+            *element = new_element.into_owned();
+        }
+    }
+
+    /// replace the placeholders in an event, which may be character data or an element
+    pub(crate) fn event(&mut self, event: &mut Event<'static>) {
+        match event {
+            Event::Start(element) | Event::Empty(element) => self.element(element),
+            Event::Text(text) => {
+                let new_text = text.unescape().ok().and_then(|text| self.replace(&text));
+                if let Some(new_text) = new_text {
+                    *text = BytesText::new(&new_text).into_owned();
+                }
+            }
+            Event::CData(text) => {
+                let new_text = self.replace(&String::from_utf8_lossy(text));
+                if let Some(new_text) = new_text {
+                    *text = BytesCData::new(new_text);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// replace every `{{name}}` in `value` that has a value, returning `None` if nothing
+    /// was replaced
+    fn replace(&mut self, value: &str) -> Option<String> {
+        if !value.contains(OPEN) {
+            return None;
+        }
+
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        let mut replaced = false;
+
+        while let Some(start) = rest.find(OPEN) {
+            let after_open = &rest[start + OPEN.len()..];
+            let end = match after_open.find(CLOSE) {
+                Some(end) => end,
+                None => break,
+            };
+
+            out.push_str(&rest[..start]);
+
+            let name = after_open[..end].trim();
+            match self.values.get_key_value(name) {
+                Some((name, value)) => {
+                    out.push_str(value);
+                    self.used.insert(name);
+                    replaced = true;
+                }
+                None => {
+                    out.push_str(&rest[start..start + OPEN.len() + end + CLOSE.len()]);
+                    self.unresolved.insert(name.to_string());
+                }
+            }
+
+            rest = &after_open[end + CLOSE.len()..];
+        }
+
+        out.push_str(rest);
+
+        replaced.then_some(out)
+    }
+
+    pub(crate) fn report(self) -> TemplateReport {
+        let mut unused = self
+            .values
+            .keys()
+            .filter(|name| !self.used.contains(name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        unused.sort();
+
+        TemplateReport {
+            unresolved: self.unresolved.into_iter().collect(),
+            unused,
+        }
+    }
+}
+
+#[test]
+fn replace_placeholders() {
+    let values = [("name", "WT & KO"), ("n", "42")]
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .into();
+    let mut substitution = Substitution::new(&values);
+
+    assert_eq!(
+        substitution.replace("{{name}}: n = {{ n }}").as_deref(),
+        Some("WT & KO: n = 42")
+    );
+    assert_eq!(
+        substitution.replace("{{date}} {{n}}").as_deref(),
+        Some("{{date}} 42")
+    );
+    assert_eq!(substitution.replace("{{date}}"), None);
+    assert_eq!(substitution.replace("{{ unclosed"), None);
+
+    let mut element = BytesStart::new("g").with_attributes([("inkscape:label", "{{name}}")]);
+    substitution.element(&mut element);
+    assert_eq!(
+        element.attributes().next().unwrap().unwrap().value.as_ref(),
+        b"WT &amp; KO"
+    );
+
+    let report = substitution.report();
+    assert_eq!(report.unresolved, ["date"]);
+    assert!(report.unused.is_empty());
+}
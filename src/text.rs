@@ -0,0 +1,416 @@
+use super::ids::IdGenerator;
+use super::object;
+use super::style::Style;
+use super::units::Length;
+
+use quick_xml::events::BytesEnd;
+use quick_xml::events::BytesStart;
+use quick_xml::events::BytesText;
+use quick_xml::events::Event;
+use quick_xml::name::QName;
+
+/// the line height inkscape uses when a text does not set one
+const DEFAULT_LINE_HEIGHT: f64 = 1.25;
+
+#[derive(Debug, Clone)]
+/// a `<text>` element along with everything inside of it
+///
+/// inkscape writes every line of a text as its own `<tspan sodipodi:role="line">`, which
+/// carries the position and styling of the line
+pub struct Text {
+    pub(crate) element: BytesStart<'static>,
+    /// the `<tspan>` children and character data if the element was written as
+    /// `<text> ... </text>` instead of `<text/>`
+    pub(crate) content: Option<Vec<Event<'static>>>,
+}
+
+impl Text {
+    pub(crate) fn new(element: BytesStart<'static>, content: Option<Vec<Event<'static>>>) -> Self {
+        Self { element, content }
+    }
+
+    /// the characters of the text, with a `\n` between every line. Characters outside of
+    /// the lines are included where they are, whitespace between the lines is not
+    pub fn text(&self) -> String {
+        let content = self.content.as_deref().unwrap_or_default();
+        let segments = segments(content);
+
+        if !segments.iter().any(Segment::is_line) {
+            return characters(content);
+        }
+
+        let mut text = String::new();
+        let mut first_line = true;
+        for segment in &segments {
+            match segment {
+                Segment::Line(line) => {
+                    if !first_line {
+                        text.push('\n');
+                    }
+                    first_line = false;
+                    text.push_str(&characters(line));
+                }
+                Segment::Other(events) => {
+                    let characters = characters(events);
+                    if !characters.trim().is_empty() {
+                        text.push_str(&characters);
+                    }
+                }
+            }
+        }
+
+        text
+    }
+
+    /// replace the characters of the text, keeping the position and styling of every line.
+    ///
+    /// Each line of `text` (split on `\n`) goes into one line `<tspan>`. Lines that are
+    /// no longer needed are removed, and extra lines are copies of the last one, moved down
+    /// by the distance between lines. Copied elements are given new ids from `ids`.
+    /// Characters outside of the lines are removed, since they are replaced by `text`
+    pub(crate) fn set_text(&mut self, text: &str, ids: &mut IdGenerator) {
+        let content = self.content.take().unwrap_or_default();
+        let Lines {
+            before,
+            mut lines,
+            after,
+        } = split_lines(content);
+
+        if lines.is_empty() {
+            let mut content = before;
+            set_characters(&mut content, text);
+            self.content = Some(content);
+            return;
+        }
+
+        let new_lines = text.split('\n').collect::<Vec<_>>();
+
+        let step = self.line_step(&lines);
+        while lines.len() < new_lines.len() {
+            // `lines` is never empty here
+            let mut line = lines[lines.len() - 1].clone();
+
+            if let Some(Event::Start(element) | Event::Empty(element)) = line.first_mut() {
+                if let (Some(step), Some(y)) = (step, number_attribute(element, b"y")) {
+                    object::set_attribute(element, "y", Some(&(y + step).to_string()));
+                }
+            }
+
+            for event in line.iter_mut() {
+                if let Event::Start(element) | Event::Empty(element) = event {
+                    if let Some(id) = object::attribute_value(element, b"id") {
+                        object::set_attribute(element, "id", Some(&ids.generate(&id)));
+                    }
+                }
+            }
+
+            lines.push(line);
+        }
+        lines.truncate(new_lines.len());
+
+        for (line, text) in lines.iter_mut().zip(new_lines) {
+            set_characters(line, text);
+        }
+
+        // the lines are written where the first one was, surrounded by whatever else
+        // was inside of the text
+        self.content = Some(
+            before
+                .into_iter()
+                .chain(lines.into_iter().flatten())
+                .chain(after)
+                .collect(),
+        );
+    }
+
+    /// the distance between two lines, from the positions of the last two lines or
+    /// otherwise from the font size and line height
+    fn line_step(&self, lines: &[Vec<Event<'static>>]) -> Option<f64> {
+        let y = |line: &Vec<Event<'static>>| match line.first() {
+            Some(Event::Start(element) | Event::Empty(element)) => number_attribute(element, b"y"),
+            _ => None,
+        };
+
+        if let [.., second_last, last] = lines {
+            if let (Some(a), Some(b)) = (y(second_last), y(last)) {
+                return Some(b - a);
+            }
+        }
+
+        let last_line = match lines.last().and_then(|line| line.first()) {
+            Some(Event::Start(element) | Event::Empty(element)) => object::element_style(element),
+            _ => Style::default(),
+        };
+        let text = object::element_style(&self.element);
+
+        let property = |name| last_line.get(name).or_else(|| text.get(name));
+
+        let font_size = property("font-size")?.parse::<Length>().ok()?.value;
+        let line_height = property("line-height")
+            .and_then(|height| height.parse::<f64>().ok())
+            .unwrap_or(DEFAULT_LINE_HEIGHT);
+
+        Some(font_size * line_height)
+    }
+}
+
+/// a single event, or a whole element, directly inside of a `<text>`
+enum Segment {
+    /// a `<tspan sodipodi:role="line">`, including its start and end events
+    Line(Vec<Event<'static>>),
+    /// anything else, such as whitespace, character data or a `<tspan>` that is not a line
+    Other(Vec<Event<'static>>),
+}
+
+impl Segment {
+    fn is_line(&self) -> bool {
+        matches!(self, Segment::Line(_))
+    }
+}
+
+/// split the content of a `<text>` into single events and whole elements
+fn segments(content: &[Event<'static>]) -> Vec<Segment> {
+    let is_line = |element: &BytesStart<'_>| {
+        element.name() == QName(b"tspan")
+            && object::attribute_value(element, b"sodipodi:role").as_deref() == Some("line")
+    };
+
+    let mut segments = Vec::new();
+    let mut current: Option<Vec<Event<'static>>> = None;
+    let mut depth: usize = 0;
+
+    for event in content {
+        if let Some(element) = current.as_mut() {
+            element.push(event.clone());
+
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => {
+                    let element = current.take().unwrap_or_default();
+                    match element.first() {
+                        Some(Event::Start(start)) if is_line(start) => {
+                            segments.push(Segment::Line(element))
+                        }
+                        _ => segments.push(Segment::Other(element)),
+                    }
+                }
+                Event::End(_) => depth -= 1,
+                _ => (),
+            }
+
+            continue;
+        }
+
+        match event {
+            Event::Start(_) => current = Some(vec![event.clone()]),
+            Event::Empty(element) if is_line(element) => {
+                segments.push(Segment::Line(vec![event.clone()]))
+            }
+            other => segments.push(Segment::Other(vec![other.clone()])),
+        }
+    }
+
+    // an element that is never closed
+    if let Some(element) = current {
+        segments.push(Segment::Other(element));
+    }
+
+    segments
+}
+
+/// the content of a `<text>` split into its lines
+struct Lines {
+    /// everything before the first line
+    before: Vec<Event<'static>>,
+    /// every line `<tspan>`, including its start and end events
+    lines: Vec<Vec<Event<'static>>>,
+    /// everything after the last line
+    after: Vec<Event<'static>>,
+}
+
+/// split the content of a `<text>` into its line `<tspan>`s. Without any lines, all of
+/// `content` is in `before`. Whatever is between the lines is dropped, and so are
+/// characters before or after them, which leaves only whitespace and other elements
+fn split_lines(content: Vec<Event<'static>>) -> Lines {
+    let segments = segments(&content);
+
+    if !segments.iter().any(Segment::is_line) {
+        return Lines {
+            before: content,
+            lines: Vec::new(),
+            after: Vec::new(),
+        };
+    }
+
+    let mut lines = Vec::new();
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+
+    for segment in segments {
+        match segment {
+            Segment::Line(line) => {
+                lines.push(line);
+                after.clear();
+            }
+            Segment::Other(events) if characters(&events).trim().is_empty() => {
+                if lines.is_empty() {
+                    before.extend(events);
+                } else {
+                    after.extend(events);
+                }
+            }
+            Segment::Other(_) => (),
+        }
+    }
+
+    Lines {
+        before,
+        lines,
+        after,
+    }
+}
+
+/// all character data in `events`
+fn characters(events: &[Event<'static>]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) => text.unescape().ok().map(|text| text.into_owned()),
+            Event::CData(text) => Some(String::from_utf8_lossy(text).into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// put `text` where the first character data in `events` is, removing all other character
+/// data. This keeps the styling of the first `<tspan>` the text was in, and leaves the runs
+/// alone if the characters are unchanged
+fn set_characters(events: &mut Vec<Event<'static>>, text: &str) {
+    if characters(events) == text {
+        return;
+    }
+
+    let is_text = |event: &Event<'static>| matches!(event, Event::Text(_) | Event::CData(_));
+    let new_text = Event::Text(BytesText::new(text).into_owned());
+
+    match events.iter().position(is_text) {
+        Some(first) => {
+            events[first] = new_text;
+
+            let mut index = 0;
+            events.retain(|event| {
+                index += 1;
+                index - 1 == first || !is_text(event)
+            });
+        }
+        None => match events.as_slice() {
+            // an empty `<tspan/>` line
+            [Event::Empty(element)] => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                let start = Event::Start(element.clone());
+                *events = vec![start, new_text, Event::End(BytesEnd::new(name))];
+            }
+            // put the text directly after the start of the line
+            [Event::Start(_), ..] => events.insert(1, new_text),
+            _ => events.push(new_text),
+        },
+    }
+}
+
+/// an attribute that is a single number, such as the `y` of a line
+fn number_attribute(element: &BytesStart<'_>, key: &[u8]) -> Option<f64> {
+    object::attribute_value(element, key)?.trim().parse().ok()
+}
+
+#[cfg(test)]
+fn parse_text(svg: &str) -> Text {
+    let mut reader = quick_xml::Reader::from_str(svg);
+    let element = match reader.read_event().unwrap() {
+        Event::Start(element) => element.into_owned(),
+        other => panic!("unexpected event {other:?}"),
+    };
+    let mut content = Vec::new();
+    loop {
+        match reader.read_event().unwrap() {
+            Event::End(end) if end.name() == QName(b"text") => break,
+            event => content.push(event.into_owned()),
+        }
+    }
+
+    Text::new(element, Some(content))
+}
+
+#[cfg(test)]
+fn write_content(text: &Text) -> String {
+    let mut output = Vec::new();
+    let mut writer = quick_xml::Writer::new(&mut output);
+    for event in text.content.as_ref().unwrap() {
+        writer.write_event(event).unwrap();
+    }
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn replace_lines() {
+    let mut text = parse_text(
+        r#"<text xml:space="preserve" style="font-size:10px;line-height:1.5" x="5" y="20" id="text1"><tspan sodipodi:role="line" id="tspan1" x="5" y="20"><tspan style="font-weight:bold" id="tspan2">n</tspan> = 0</tspan></text>"#,
+    );
+    assert_eq!(text.text(), "n = 0");
+
+    let used = ["text1", "tspan1", "tspan2"].map(String::from).into();
+    let mut ids = IdGenerator::new(used);
+
+    text.set_text("n = 42\np < 0.05", &mut ids);
+    assert_eq!(text.text(), "n = 42\np < 0.05");
+
+    let output = write_content(&text);
+
+    // the bold run keeps its styling, and the new line is moved down by 10 * 1.5
+    assert_eq!(
+        output,
+        concat!(
+            r#"<tspan sodipodi:role="line" id="tspan1" x="5" y="20"><tspan style="font-weight:bold" id="tspan2">n = 42</tspan></tspan>"#,
+            r#"<tspan sodipodi:role="line" id="tspan3" x="5" y="35"><tspan style="font-weight:bold" id="tspan4">p &lt; 0.05</tspan></tspan>"#,
+        )
+    );
+
+    text.set_text("done", &mut ids);
+    assert_eq!(text.text(), "done");
+}
+
+#[test]
+fn unchanged_line_keeps_runs() {
+    let mut text = parse_text(
+        r#"<text style="font-size:10px" id="text1"><tspan sodipodi:role="line" id="tspan1" y="20"><tspan style="font-weight:bold" id="tspan2">n</tspan> = 0</tspan></text>"#,
+    );
+
+    let mut ids = IdGenerator::new(["text1", "tspan1", "tspan2"].map(String::from).into());
+    text.set_text("n = 0\nnext", &mut ids);
+
+    assert_eq!(text.text(), "n = 0\nnext");
+    assert!(write_content(&text).contains(
+        r#"<tspan style="font-weight:bold" id="tspan2">n</tspan> = 0</tspan><tspan sodipodi:role="line" id="tspan3" y="32.5"><tspan style="font-weight:bold" id="tspan4">next</tspan></tspan>"#
+    ));
+}
+
+#[test]
+fn text_outside_of_lines() {
+    let mut text = parse_text(r#"<text id="text1">Hello <tspan id="tspan1">world</tspan></text>"#);
+    assert_eq!(text.text(), "Hello world");
+
+    let mut ids = IdGenerator::new(["text1", "tspan1"].map(String::from).into());
+    text.set_text("Goodbye", &mut ids);
+    assert_eq!(text.text(), "Goodbye");
+    assert!(!write_content(&text).contains("Hello"));
+
+    let mut text = parse_text(
+        r#"<text id="text1">
+    <tspan sodipodi:role="line" id="tspan1">first</tspan>
+    <tspan sodipodi:role="line" id="tspan2">second</tspan> and more</text>"#,
+    );
+    assert_eq!(text.text(), "first\nsecond and more");
+
+    text.set_text("one\ntwo", &mut ids);
+    assert_eq!(text.text(), "one\ntwo");
+    assert!(!write_content(&text).contains("more"));
+}
@@ -0,0 +1,290 @@
+use super::error::*;
+
+use std::str::FromStr;
+
+/// An affine transformation, as written in a `transform` attribute
+///
+/// The fields are the six values of `matrix(a, b, c, d, e, f)`, which maps a point
+/// `(x, y)` to `(a*x + c*y + e, b*x + d*y + f)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self::matrix(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+    pub const fn matrix(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+
+    pub const fn translate(tx: f64, ty: f64) -> Self {
+        Self::matrix(1.0, 0.0, 0.0, 1.0, tx, ty)
+    }
+
+    pub const fn scale(sx: f64, sy: f64) -> Self {
+        Self::matrix(sx, 0.0, 0.0, sy, 0.0, 0.0)
+    }
+
+    /// rotate by `angle` degrees around the origin
+    pub fn rotate(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::matrix(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// skew along the x axis by `angle` degrees
+    pub fn skew_x(angle: f64) -> Self {
+        Self::matrix(1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0)
+    }
+
+    /// skew along the y axis by `angle` degrees
+    pub fn skew_y(angle: f64) -> Self {
+        Self::matrix(1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0)
+    }
+
+    /// the transform that applies `inner` first, and then `self`. This is how the transform
+    /// of an element combines with the transform of the group it is in, and how
+    /// `transform="self inner"` is evaluated
+    pub fn then(&self, inner: &Transform) -> Transform {
+        Self::matrix(
+            self.a * inner.a + self.c * inner.b,
+            self.b * inner.a + self.d * inner.b,
+            self.a * inner.c + self.c * inner.d,
+            self.b * inner.c + self.d * inner.d,
+            self.a * inner.e + self.c * inner.f + self.e,
+            self.b * inner.e + self.d * inner.f + self.f,
+        )
+    }
+
+    /// map a point through the transform
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    /// how much the transform stretches lengths along the x and y axis. Unlike the size of the
+    /// [`Transform::bounding_box`], this does not change when rotating
+    pub fn axis_scale(&self) -> (f64, f64) {
+        (self.a.hypot(self.b), self.c.hypot(self.d))
+    }
+
+    /// the axis aligned box containing the rectangle `(x, y, width, height)` after
+    /// it has been transformed
+    pub fn bounding_box(&self, x: f64, y: f64, width: f64, height: f64) -> BoundingBox {
+        let corners = [
+            self.apply(x, y),
+            self.apply(x + width, y),
+            self.apply(x, y + height),
+            self.apply(x + width, y + height),
+        ];
+
+        let (min_x, min_y, max_x, max_y) = corners.iter().fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+
+        BoundingBox {
+            x: min_x,
+            y: min_y,
+            width: max_x - min_x,
+            height: max_y - min_y,
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = ParseTransformError;
+
+    /// parse a transform list such as `translate(10, 20) rotate(45)`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason| ParseTransformError::new(s.to_string(), reason);
+
+        let mut out = Transform::IDENTITY;
+        let mut rest = s;
+
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+
+            if rest.is_empty() {
+                return Ok(out);
+            }
+
+            let open = rest.find('(').ok_or_else(|| error("missing `(`"))?;
+            let close = rest.find(')').ok_or_else(|| error("missing `)`"))?;
+            if close < open {
+                return Err(error("`)` before `(`"));
+            }
+
+            let name = rest[..open].trim();
+            let args = numbers(&rest[open + 1..close]).ok_or_else(|| error("invalid number"))?;
+
+            let transform = match (name, args.as_slice()) {
+                ("matrix", &[a, b, c, d, e, f]) => Transform::matrix(a, b, c, d, e, f),
+                ("translate", &[tx]) => Transform::translate(tx, 0.0),
+                ("translate", &[tx, ty]) => Transform::translate(tx, ty),
+                ("scale", &[s]) => Transform::scale(s, s),
+                ("scale", &[sx, sy]) => Transform::scale(sx, sy),
+                ("rotate", &[angle]) => Transform::rotate(angle),
+                ("rotate", &[angle, cx, cy]) => Transform::translate(cx, cy)
+                    .then(&Transform::rotate(angle))
+                    .then(&Transform::translate(-cx, -cy)),
+                ("skewX", &[angle]) => Transform::skew_x(angle),
+                ("skewY", &[angle]) => Transform::skew_y(angle),
+                ("matrix" | "translate" | "scale" | "rotate" | "skewX" | "skewY", _) => {
+                    return Err(error("wrong number of arguments"))
+                }
+                _ => return Err(error("unknown transform function")),
+            };
+
+            out = out.then(&transform);
+            rest = &rest[close + 1..];
+        }
+    }
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "matrix({},{},{},{},{},{})",
+            self.a, self.b, self.c, self.d, self.e, self.f
+        )
+    }
+}
+
+/// split the arguments of a transform function into numbers. Numbers may be separated by
+/// whitespace, commas, or nothing at all when the next number starts with a sign (`10-5`)
+fn numbers(args: &str) -> Option<Vec<f64>> {
+    let bytes = args.as_bytes();
+    let mut out = Vec::new();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        if bytes[start] == b',' || bytes[start].is_ascii_whitespace() {
+            start += 1;
+            continue;
+        }
+
+        let mut end = start;
+        let mut seen_dot = false;
+        let mut seen_exponent = false;
+
+        while end < bytes.len() {
+            match bytes[end] {
+                b'+' | b'-' if end == start => (),
+                // a sign directly after an exponent belongs to the exponent
+                b'+' | b'-' if matches!(bytes[end - 1], b'e' | b'E') => (),
+                b'0'..=b'9' => (),
+                b'.' if !seen_dot && !seen_exponent => seen_dot = true,
+                b'e' | b'E' if !seen_exponent && end > start => seen_exponent = true,
+                _ => break,
+            }
+            end += 1;
+        }
+
+        if end == start {
+            return None;
+        }
+
+        out.push(args[start..end].parse().ok()?);
+        start = end;
+    }
+
+    Some(out)
+}
+
+/// An axis aligned rectangle in the user units of the document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[test]
+fn parse_transform_lists() {
+    let close = |a: Transform, b: Transform| {
+        let a = [a.a, a.b, a.c, a.d, a.e, a.f];
+        let b = [b.a, b.b, b.c, b.d, b.e, b.f];
+        assert!(
+            a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-9),
+            "{a:?} != {b:?}"
+        );
+    };
+
+    close(
+        "matrix(0.77134943,0,0,0.77134943,205.87103,-24.982763)"
+            .parse()
+            .unwrap(),
+        Transform::matrix(0.77134943, 0.0, 0.0, 0.77134943, 205.87103, -24.982763),
+    );
+    close(
+        "translate(-31.545218,-0.16703364)".parse().unwrap(),
+        Transform::translate(-31.545218, -0.16703364),
+    );
+    close(
+        "translate(5)".parse().unwrap(),
+        Transform::translate(5.0, 0.0),
+    );
+    close("scale(2)".parse().unwrap(), Transform::scale(2.0, 2.0));
+    close(
+        "translate(10 20) scale(2,3)".parse().unwrap(),
+        Transform::matrix(2.0, 0.0, 0.0, 3.0, 10.0, 20.0),
+    );
+    close(
+        "rotate(90)".parse().unwrap(),
+        Transform::matrix(0.0, 1.0, -1.0, 0.0, 0.0, 0.0),
+    );
+    close(
+        "rotate(90, 10, 10)".parse().unwrap(),
+        Transform::matrix(0.0, 1.0, -1.0, 0.0, 20.0, 0.0),
+    );
+    close(
+        "skewX(45)".parse().unwrap(),
+        Transform::matrix(1.0, 0.0, 1.0, 1.0, 0.0, 0.0),
+    );
+    close(
+        "translate(1e1-5)".parse().unwrap(),
+        Transform::translate(10.0, -5.0),
+    );
+    close("".parse().unwrap(), Transform::IDENTITY);
+
+    assert!("scale(1,2,3)".parse::<Transform>().is_err());
+    assert!("shear(1)".parse::<Transform>().is_err());
+    assert!("translate(1".parse::<Transform>().is_err());
+    assert!("translate(1px)".parse::<Transform>().is_err());
+}
+
+#[test]
+fn transformed_bounding_box() {
+    let bbox = "translate(10,0) rotate(90)"
+        .parse::<Transform>()
+        .unwrap()
+        .bounding_box(0.0, 0.0, 4.0, 2.0);
+
+    assert!((bbox.x - 8.0).abs() < 1e-9);
+    assert!(bbox.y.abs() < 1e-9);
+    assert!((bbox.width - 2.0).abs() < 1e-9);
+    assert!((bbox.height - 4.0).abs() < 1e-9);
+}
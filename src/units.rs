@@ -0,0 +1,307 @@
+use super::error::*;
+use super::object;
+
+use quick_xml::events::BytesStart;
+
+use std::str::FromStr;
+
+/// CSS pixels per inch, which every absolute SVG unit is defined relative to
+const PX_PER_INCH: f64 = 96.0;
+
+/// A unit that a length in an SVG document may be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// a bare number, in the user units of the document
+    User,
+    Px,
+    Mm,
+    Cm,
+    In,
+    Pt,
+    Pc,
+    /// quarter millimetres
+    Q,
+    /// a percentage of the width or height of the document's viewBox
+    Percent,
+}
+
+impl Unit {
+    /// the size of one of this unit in CSS pixels, if it is an absolute unit
+    fn px(self) -> Option<f64> {
+        let px = match self {
+            Self::Px => 1.0,
+            Self::In => PX_PER_INCH,
+            Self::Cm => PX_PER_INCH / 2.54,
+            Self::Mm => PX_PER_INCH / 25.4,
+            Self::Q => PX_PER_INCH / 101.6,
+            Self::Pt => PX_PER_INCH / 72.0,
+            Self::Pc => PX_PER_INCH / 6.0,
+            Self::User | Self::Percent => return None,
+        };
+
+        Some(px)
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::User => "",
+            Self::Px => "px",
+            Self::Mm => "mm",
+            Self::Cm => "cm",
+            Self::In => "in",
+            Self::Pt => "pt",
+            Self::Pc => "pc",
+            Self::Q => "Q",
+            Self::Percent => "%",
+        }
+    }
+}
+
+/// A number with an (optional) SVG unit, as written in `width="10mm"`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Length {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Self { value, unit }
+    }
+
+    /// a length in the user units of the document
+    pub fn user(value: f64) -> Self {
+        Self::new(value, Unit::User)
+    }
+}
+
+impl FromStr for Length {
+    type Err = ParseLengthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const UNITS: [Unit; 8] = [
+            Unit::Px,
+            Unit::Mm,
+            Unit::Cm,
+            Unit::In,
+            Unit::Pt,
+            Unit::Pc,
+            Unit::Q,
+            Unit::Percent,
+        ];
+
+        let trimmed = s.trim();
+
+        let (number, unit) = UNITS
+            .iter()
+            .find_map(|unit| {
+                trimmed
+                    .strip_suffix(unit.suffix())
+                    .map(|number| (number, *unit))
+            })
+            .unwrap_or((trimmed, Unit::User));
+
+        let value = number
+            .trim_end()
+            .parse()
+            .map_err(|error| ParseLengthError::new(error, s.to_string()))?;
+
+        Ok(Self { value, unit })
+    }
+}
+
+impl std::fmt::Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.value, self.unit.suffix())
+    }
+}
+
+/// The direction a length is measured in, which decides what a percentage is relative to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// How the user units of a document map to physical units, from the `width`, `height`
+/// and `viewBox` attributes of its root `<svg>` element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// `min-x`, `min-y`, `width`, `height` of the user coordinate system
+    view_box: [f64; 4],
+    /// the size of the document in CSS pixels, if it has an absolute size
+    size_px: Option<(f64, f64)>,
+}
+
+impl Viewport {
+    pub(crate) fn from_root(root: &BytesStart<'_>) -> Result<Self, ViewportError> {
+        let length = |key: &[u8], dimension| -> Result<Option<Length>, ViewportError> {
+            object::attribute_value(root, key)
+                .map(|value| value.parse::<Length>())
+                .transpose()
+                .map_err(|error| DimensionParse::new(error, dimension).into())
+        };
+
+        let width = length(b"width", DimensionOrId::Width)?;
+        let height = length(b"height", DimensionOrId::Height)?;
+
+        // lengths on the root element are relative to the window, not the document, so a
+        // bare number is the same as pixels
+        let px = |length: Option<Length>| match length {
+            Some(Length {
+                value,
+                unit: Unit::User,
+            }) => Some(value),
+            Some(Length { value, unit }) => unit.px().map(|px| value * px),
+            None => None,
+        };
+
+        let size_px = px(width).zip(px(height));
+
+        let view_box = match (object::attribute_value(root, b"viewBox"), size_px) {
+            (Some(view_box), _) => parse_view_box(&view_box)?,
+            // without a viewBox, one user unit is one pixel
+            (None, Some((width, height))) => [0.0, 0.0, width, height],
+            (None, None) => return Err(MissingViewBox.into()),
+        };
+
+        Ok(Self { view_box, size_px })
+    }
+
+    /// `min-x`, `min-y`, `width`, `height` of the user coordinate system
+    pub fn view_box(&self) -> [f64; 4] {
+        self.view_box
+    }
+
+    /// the size of a single user unit in CSS pixels
+    fn px_per_user_unit(&self, axis: Axis) -> f64 {
+        let [_, _, view_width, view_height] = self.view_box;
+
+        match (self.size_px, axis) {
+            (Some((width, _)), Axis::Horizontal) => width / view_width,
+            (Some((_, height)), Axis::Vertical) => height / view_height,
+            // a document without a physical size is drawn at one pixel per user unit
+            (None, _) => 1.0,
+        }
+    }
+
+    /// the length of the viewBox along `axis`, which percentages are relative to
+    fn view_length(&self, axis: Axis) -> f64 {
+        match axis {
+            Axis::Horizontal => self.view_box[2],
+            Axis::Vertical => self.view_box[3],
+        }
+    }
+
+    /// convert a length written in the document to user units
+    pub fn to_user_units(&self, length: Length, axis: Axis) -> f64 {
+        match length.unit {
+            Unit::User => length.value,
+            Unit::Percent => length.value / 100.0 * self.view_length(axis),
+            unit => {
+                // every other unit is absolute
+                let px = unit.px().unwrap_or(1.0);
+                length.value * px / self.px_per_user_unit(axis)
+            }
+        }
+    }
+
+    /// convert a value in user units to another unit
+    pub fn from_user_units(&self, value: f64, unit: Unit, axis: Axis) -> f64 {
+        match unit {
+            Unit::User => value,
+            Unit::Percent => value / self.view_length(axis) * 100.0,
+            unit => {
+                let px = unit.px().unwrap_or(1.0);
+                value * self.px_per_user_unit(axis) / px
+            }
+        }
+    }
+
+    /// convert a value in user units to pixels of an image rendered at `dpi`
+    pub fn user_units_to_pixels(&self, value: f64, dpi: f64, axis: Axis) -> f64 {
+        self.from_user_units(value, Unit::In, axis) * dpi
+    }
+}
+
+/// parse a `viewBox` attribute of four numbers separated by whitespace and / or commas
+pub(crate) fn parse_view_box(view_box: &str) -> Result<[f64; 4], InvalidViewBox> {
+    let numbers = view_box
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(str::parse::<f64>)
+        .collect::<Result<Vec<_>, _>>();
+
+    match numbers.as_deref() {
+        Ok(&[min_x, min_y, width, height]) if width > 0.0 && height > 0.0 => {
+            Ok([min_x, min_y, width, height])
+        }
+        _ => Err(InvalidViewBox::new(view_box.to_string())),
+    }
+}
+
+#[test]
+fn parse_lengths() {
+    assert_eq!("10".parse::<Length>().unwrap(), Length::user(10.0));
+    assert_eq!(
+        "10.5mm".parse::<Length>().unwrap(),
+        Length::new(10.5, Unit::Mm)
+    );
+    assert_eq!(
+        " 2in ".parse::<Length>().unwrap(),
+        Length::new(2.0, Unit::In)
+    );
+    assert_eq!(
+        "-1e2px".parse::<Length>().unwrap(),
+        Length::new(-100.0, Unit::Px)
+    );
+    assert_eq!(
+        "50%".parse::<Length>().unwrap(),
+        Length::new(50.0, Unit::Percent)
+    );
+    assert!("10em".parse::<Length>().is_err());
+    assert!("mm".parse::<Length>().is_err());
+}
+
+#[test]
+fn convert_through_viewport() {
+    // an A4 page drawn in millimetres, as inkscape writes by default
+    let root = BytesStart::new("svg").with_attributes([
+        ("width", "210mm"),
+        ("height", "297mm"),
+        ("viewBox", "0 0 210 297"),
+    ]);
+    let viewport = Viewport::from_root(&root).unwrap();
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+    assert!(close(
+        viewport.to_user_units(Length::new(1.0, Unit::In), Axis::Horizontal),
+        25.4
+    ));
+    assert!(close(
+        viewport.to_user_units(Length::new(50.0, Unit::Percent), Axis::Vertical),
+        148.5
+    ));
+    assert!(close(
+        viewport.from_user_units(25.4, Unit::In, Axis::Horizontal),
+        1.0
+    ));
+    assert!(close(
+        viewport.from_user_units(10.0, Unit::Pt, Axis::Horizontal),
+        10.0 / 25.4 * 72.0
+    ));
+    assert!(close(
+        viewport.user_units_to_pixels(25.4, 300.0, Axis::Vertical),
+        300.0
+    ));
+
+    // without a viewBox the user units are pixels
+    let root = BytesStart::new("svg").with_attributes([("width", "640"), ("height", "480")]);
+    let viewport = Viewport::from_root(&root).unwrap();
+    assert_eq!(viewport.view_box(), [0.0, 0.0, 640.0, 480.0]);
+    assert!(close(
+        viewport.from_user_units(96.0, Unit::In, Axis::Horizontal),
+        1.0
+    ));
+}